@@ -1,50 +1,279 @@
-/// A builder to create a [`Client`] with a connection.
+use std::time::Duration;
+
+#[cfg(feature = "with-rustls")]
+use {
+    rustls::{ClientConfig, RootCertStore},
+    std::sync::Arc,
+};
+
+#[cfg(feature = "runtime-tokio")]
+use crate::AsyncClient;
+
+#[cfg(feature = "runtime-sync")]
+use crate::SyncClient;
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+use crate::sasl;
+
+use crate::client::{apop_timestamp, Limits, Timeouts};
+use crate::protocol::{MAX_LINE, MAX_RESPONSE};
+use crate::{Capabilities, Pop3Error, Result};
+
+/// How the connection to the server is protected
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Security {
+    /// No protection at all, usually on port 110
+    #[default]
+    Plain,
+    /// Implicit TLS (POP3S), usually on port 995
+    #[cfg(feature = "with-rustls")]
+    Tls,
+    /// Plaintext connection upgraded with `STLS`, failing if the server does not support it
+    #[cfg(feature = "with-rustls")]
+    StlsRequired,
+    /// Plaintext connection upgraded with `STLS` if the server advertises it in `CAPA`, left in plaintext otherwise
+    #[cfg(feature = "with-rustls")]
+    StlsOpportunistic,
+}
+
+impl Security {
+    /// The well-known port of the mode
+    fn default_port(self) -> u16 {
+        match self {
+            #[cfg(feature = "with-rustls")]
+            Self::Tls => 995,
+            _ => 110,
+        }
+    }
+}
+
+/// An authorization method the [`Builder`] may use, see [`Builder::mechanisms`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mechanism {
+    /// `SCRAM-SHA-256-PLUS` SASL mechanism, bound to the TLS connection
+    ScramSha256Plus,
+    /// `SCRAM-SHA-256` SASL mechanism
+    ScramSha256,
+    /// `SCRAM-SHA-1` SASL mechanism
+    ScramSha1,
+    /// `CRAM-MD5` SASL mechanism
+    CramMd5,
+    /// `APOP` command, when the server greeting has a timestamp
+    Apop,
+    /// `PLAIN` SASL mechanism
+    Plain,
+    /// `LOGIN` SASL mechanism
+    Login,
+    /// `USER` and `PASS` commands
+    User,
+    /// `OAUTHBEARER` SASL mechanism, requires an OAuth token
+    OAuthBearer,
+    /// `XOAUTH2` SASL mechanism, requires an OAuth token
+    XOAuth2,
+}
+
+impl Mechanism {
+    /// Preference used when none is given: the ones not revealing the password come first
+    const DEFAULT: [Self; 10] = [
+        Self::ScramSha256Plus,
+        Self::ScramSha256,
+        Self::ScramSha1,
+        Self::CramMd5,
+        Self::Apop,
+        Self::Plain,
+        Self::Login,
+        Self::User,
+        Self::OAuthBearer,
+        Self::XOAuth2,
+    ];
+
+    /// Name of the SASL mechanism, `None` for the commands of the POP3 itself
+    fn sasl_name(self) -> Option<&'static str> {
+        match self {
+            Self::ScramSha256Plus => Some("SCRAM-SHA-256-PLUS"),
+            Self::ScramSha256     => Some("SCRAM-SHA-256"),
+            Self::ScramSha1       => Some("SCRAM-SHA-1"),
+            Self::CramMd5         => Some("CRAM-MD5"),
+            Self::Plain           => Some("PLAIN"),
+            Self::Login           => Some("LOGIN"),
+            Self::OAuthBearer     => Some("OAUTHBEARER"),
+            Self::XOAuth2         => Some("XOAUTH2"),
+            Self::Apop | Self::User => None,
+        }
+    }
+
+    fn uses_token(self) -> bool {
+        matches!(self, Self::OAuthBearer | Self::XOAuth2)
+    }
+
+    /// The server supports the mechanism, as far as can be told from its capabilities and greeting
+    ///
+    /// Without capabilities, that is when the server does not support `CAPA`, only the [RFC 1939] methods are assumed.
+    ///
+    /// [RFC 1939]: https://tools.ietf.org/html/rfc1939
+    fn is_available(self, capabilities: Option<&Capabilities>, greeting: &str, tls: bool) -> bool {
+        match self {
+            Self::Apop => apop_timestamp(greeting).is_some(),
+            Self::User => capabilities.is_none_or(|c| c.supports_user()),
+            Self::ScramSha256Plus if !tls => false,
+            m => capabilities.is_some_and(|c| c.supports_sasl_mechanism(m.sasl_name().unwrap_or_default())),
+        }
+    }
+}
+
+/// Secret the session is authorized with
+enum Secret {
+    Password(String),
+    Token(String),
+}
+
+struct Credentials {
+    username: String,
+    secret:   Secret,
+}
+
+/// A builder to create a client with a connection.
+///
+/// It is the single entry point for a fully configured session: the connection security, timeouts, TLS config and credentials.
+/// The client it produces is already authorized, if credentials were given.
 ///
-/// As it is possible to create the [`Client`] without using `Builder`, we recommend to only use in when you with to define a custom [`ClientConfig`] for the TLS connection.
+/// # Example
+/// ```no_run
+/// # use std::result::Result;
+/// # use std::time::Duration;
+/// # use pop3_client::{Builder, Pop3Error};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Pop3Error> {
+/// let mut client = Builder::new("my.host.com")
+///     .port(110)
+///     .read_timeout(Duration::from_secs(30))
+///     .password("sweet_username", "very_secret_password")
+///     .connect_async()
+///     .await?;
 ///
-/// [`Client`]: struct.Client
-/// [`ClientConfig`]: https://docs.rs/rustls/0.15.2/rustls/struct.ClientConfig.html
+/// let (messages, octets) = client.stat().await?;
+/// #    Ok(())
+/// # }
+/// ```
 pub struct Builder {
     #[cfg(feature = "with-rustls")]
     config: Arc<ClientConfig>,
+    timeouts: Timeouts,
+    max_line: usize,
+    max_response: usize,
+    host: String,
+    port: Option<u16>,
+    security: Security,
+    credentials: Option<Credentials>,
+    mechanisms: Option<Vec<Mechanism>>,
 }
 
+// Not derivable with a TLS config
+#[cfg_attr(not(feature = "with-rustls"), allow(clippy::derivable_impls))]
 impl Default for Builder {
-    #[cfg(not(feature = "with-rustls"))]
     fn default() -> Self {
-        Self {}
+        Self {
+            #[cfg(feature = "with-rustls")]
+            config: Arc::new(default_tls_config()),
+            timeouts: Timeouts::default(),
+            max_line: MAX_LINE,
+            max_response: MAX_RESPONSE,
+            host: String::new(),
+            port: None,
+            security: Security::default(),
+            credentials: None,
+            mechanisms: None,
+        }
     }
+}
 
-    #[cfg(feature = "with-rustls")]
-    fn default() -> Self {
-        let mut config = ClientConfig::new();
-        config
-            .root_store
-            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
-
-        let config = Arc::new(config);
+#[cfg(feature = "with-rustls")]
+fn default_tls_config() -> ClientConfig {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
 
-        Self { config }
-    }
+    ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth()
 }
 
 impl Builder {
+    /// Start the configuration of a session with the given host
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Port to connect to, by default the well-known one of the [`Security`] mode: 995 for implicit TLS, 110 otherwise
+    pub fn port(&mut self, port: u16) -> &mut Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// How the connection is protected, plaintext by default
+    pub fn security(&mut self, security: Security) -> &mut Self {
+        self.security = security;
+        self
+    }
+
+    /// Authorize with the username and password
+    ///
+    /// The method is chosen among the ones the server supports, following the [`mechanisms`](Self::mechanisms) preference.
+    /// Note that the password is sent in the clear by `USER`, `PLAIN` and `LOGIN` over a connection without TLS.
+    pub fn password(&mut self, username: &str, password: &str) -> &mut Self {
+        self.credentials = Some(Credentials {
+            username: username.into(),
+            secret:   Secret::Password(password.into()),
+        });
+        self
+    }
+
+    /// Authorize with the username and an OAuth 2.0 bearer token, using `OAUTHBEARER` or `XOAUTH2`
+    pub fn oauth(&mut self, username: &str, token: &str) -> &mut Self {
+        self.credentials = Some(Credentials {
+            username: username.into(),
+            secret:   Secret::Token(token.into()),
+        });
+        self
+    }
+
+    /// Authorization methods to choose from, most preferred first
+    ///
+    /// The first one supported by the server and suitable for the credentials is used, without falling back to the others if it fails.
+    /// By default the methods not revealing the password are preferred.
+    pub fn mechanisms(&mut self, mechanisms: &[Mechanism]) -> &mut Self {
+        self.mechanisms = Some(mechanisms.to_vec());
+        self
+    }
+
     /// Define a custom config for the TLS connection
     ///
     /// # Example
     /// ```no_run
     /// # use std::result::Result;
-    /// # use pop3_client::Builder;
-    ///   use rustls::ClientConfig;
+    /// # use pop3_client::{Builder, Pop3Error, Security};
+    ///   use rustls::{ClientConfig, RootCertStore};
     /// #
-    /// # fn main() -> Result<(), String> {
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
     ///
-    /// let mut config = ClientConfig::new();
-    /// config
-    ///     .root_store
-    ///     .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    /// let roots = RootCertStore {
+    ///     roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    /// };
     ///
-    /// let client = Builder::default().rustls_config(config).connect("my.host.com", 995)?;
+    /// let config = ClientConfig::builder()
+    ///     .with_root_certificates(roots)
+    ///     .with_no_client_auth();
+    ///
+    /// let client = Builder::new("my.host.com")
+    ///     .security(Security::Tls)
+    ///     .rustls_config(config)
+    ///     .connect_async()
+    ///     .await?;
     /// #    Ok(())
     /// # }
     /// ```
@@ -53,4 +282,275 @@ impl Builder {
         self.config = Arc::new(config);
         self
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "with-rustls")]
+    pub(crate) fn tls_config(&self) -> Arc<ClientConfig> {
+        self.config.clone()
+    }
+
+    /// Limit the time to establish the connection, including the handshake of implicit TLS
+    ///
+    /// A timeout results in [`Pop3Error::Timeout`](crate::Pop3Error::Timeout) with the `connect` command.
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Limit the time to wait for the server to send data while reading a reply
+    ///
+    /// A timeout results in [`Pop3Error::Timeout`](crate::Pop3Error::Timeout) with the command whose reply was awaited.
+    /// The session is then over: the later calls fail with [`Pop3Error::ConnectionClosed`](crate::Pop3Error::ConnectionClosed), as the late reply can not be told apart.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use std::time::Duration;
+    /// # use pop3_client::{Builder, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// let client = Builder::new("my.host.com")
+    ///     .connect_timeout(Duration::from_secs(10))
+    ///     .read_timeout(Duration::from_secs(30))
+    ///     .connect_async()
+    ///     .await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
+    /// Limit the time to send a command to the server
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.write = Some(timeout);
+        self
+    }
+
+    /// Limit the duration of the whole session, counted from the start of the connection
+    ///
+    /// Any operation after the deadline fails with [`Pop3Error::Timeout`](crate::Pop3Error::Timeout), and the other timeouts are cut short to not exceed it.
+    pub fn session_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.session = Some(timeout);
+        self
+    }
+
+    /// Limit the length of a single line sent by the server, 1 MiB by default
+    ///
+    /// A longer line results in [`Pop3Error::ResponseTooLarge`](crate::Pop3Error::ResponseTooLarge) and closes the session.
+    pub fn max_line_length(&mut self, length: usize) -> &mut Self {
+        self.max_line = length;
+        self
+    }
+
+    /// Limit the total size of a multiline reply held in memory, 128 MiB by default
+    ///
+    /// A larger reply results in [`Pop3Error::ResponseTooLarge`](crate::Pop3Error::ResponseTooLarge) and closes the session.
+    /// The limit can be overridden for a single message with `retr_with_limit`, and does not apply to streamed retrievals.
+    pub fn max_response_size(&mut self, size: usize) -> &mut Self {
+        self.max_response = size;
+        self
+    }
+
+    /// Start the session clock with the configured limits
+    #[cfg_attr(not(any(feature = "runtime-tokio", feature = "runtime-sync")), allow(dead_code))]
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::start(self.timeouts);
+        limits.max_line = self.max_line;
+        limits.max_response = self.max_response;
+        limits
+    }
+
+    /// Connect to the configured host, protect the connection and authorize if credentials were given
+    ///
+    /// # Errors
+    /// - [`Pop3Error::NoAuthMechanism`] if none of the preferred authorization methods is supported by the server
+    /// - The server may return an error response if `STLS` is required but not supported, or if permission was denied
+    #[cfg(feature = "runtime-tokio")]
+    pub async fn connect_async(&self) -> Result<AsyncClient> {
+        let limits = self.limits();
+        let port = self.port.unwrap_or(self.security.default_port());
+
+        let (mut client, tls) = match self.security {
+            Security::Plain => (AsyncClient::connect_limited(&self.host, port, limits).await?, false),
+            #[cfg(feature = "with-rustls")]
+            Security::Tls => (AsyncClient::connect_rustls(&self.host, port, self.config.clone(), limits).await?, true),
+            #[cfg(feature = "with-rustls")]
+            Security::StlsRequired => {
+                let mut client = AsyncClient::connect_limited(&self.host, port, limits).await?;
+                client.stls_rustls(self.config.clone()).await?;
+                (client, true)
+            }
+            #[cfg(feature = "with-rustls")]
+            Security::StlsOpportunistic => {
+                let mut client = AsyncClient::connect_limited(&self.host, port, limits).await?;
+                let stls = optional(client.capa().await)?.is_some_and(|c| c.supports_stls());
+
+                if stls {
+                    client.stls_rustls(self.config.clone()).await?;
+                }
+                (client, stls)
+            }
+        };
+
+        let Some(credentials) = &self.credentials else {
+            return Ok(client);
+        };
+
+        let capabilities = optional(client.capa().await)?;
+        let mechanism = self.choose(capabilities.as_ref(), client.greeting(), tls)?;
+
+        let username = credentials.username.as_str();
+        let secret = match &credentials.secret {
+            Secret::Password(s) | Secret::Token(s) => s.as_str(),
+        };
+
+        match mechanism {
+            Mechanism::ScramSha256Plus => client.authenticate(sasl::Scram::sha256(username, secret).channel_binding()).await?,
+            Mechanism::ScramSha256     => client.authenticate(sasl::Scram::sha256(username, secret)).await?,
+            Mechanism::ScramSha1       => client.authenticate(sasl::Scram::sha1(username, secret)).await?,
+            Mechanism::CramMd5         => client.authenticate(sasl::CramMd5::new(username, secret)).await?,
+            Mechanism::Apop            => client.apop_with_password(username, secret).await.map(|_| ())?,
+            Mechanism::Plain           => client.authenticate(sasl::Plain::new(username, secret)).await?,
+            Mechanism::Login           => client.authenticate(sasl::Login::new(username, secret)).await?,
+            Mechanism::User            => client.login(username, secret).await?,
+            Mechanism::OAuthBearer     => client.authenticate(sasl::OAuthBearer::new(username, secret).host(&self.host).port(port)).await?,
+            Mechanism::XOAuth2         => client.authenticate(sasl::XOAuth2::new(username, secret)).await?,
+        }
+
+        Ok(client)
+    }
+
+    /// Connect to the configured host, protect the connection and authorize if credentials were given
+    ///
+    /// # Errors
+    /// - [`Pop3Error::NoAuthMechanism`] if none of the preferred authorization methods is supported by the server
+    /// - The server may return an error response if `STLS` is required but not supported, or if permission was denied
+    #[cfg(feature = "runtime-sync")]
+    pub fn connect_sync(&self) -> Result<SyncClient> {
+        let limits = self.limits();
+        let port = self.port.unwrap_or(self.security.default_port());
+
+        let (mut client, tls) = match self.security {
+            Security::Plain => (SyncClient::connect_limited(&self.host, port, limits)?, false),
+            #[cfg(feature = "with-rustls")]
+            Security::Tls => (SyncClient::connect_rustls(&self.host, port, self.config.clone(), limits)?, true),
+            #[cfg(feature = "with-rustls")]
+            Security::StlsRequired => {
+                let mut client = SyncClient::connect_limited(&self.host, port, limits)?;
+                client.stls_rustls(self.config.clone())?;
+                (client, true)
+            }
+            #[cfg(feature = "with-rustls")]
+            Security::StlsOpportunistic => {
+                let mut client = SyncClient::connect_limited(&self.host, port, limits)?;
+                let stls = optional(client.capa())?.is_some_and(|c| c.supports_stls());
+
+                if stls {
+                    client.stls_rustls(self.config.clone())?;
+                }
+                (client, stls)
+            }
+        };
+
+        let Some(credentials) = &self.credentials else {
+            return Ok(client);
+        };
+
+        let capabilities = optional(client.capa())?;
+        let mechanism = self.choose(capabilities.as_ref(), client.greeting(), tls)?;
+
+        let username = credentials.username.as_str();
+        let secret = match &credentials.secret {
+            Secret::Password(s) | Secret::Token(s) => s.as_str(),
+        };
+
+        match mechanism {
+            Mechanism::ScramSha256Plus => client.authenticate(sasl::Scram::sha256(username, secret).channel_binding())?,
+            Mechanism::ScramSha256     => client.authenticate(sasl::Scram::sha256(username, secret))?,
+            Mechanism::ScramSha1       => client.authenticate(sasl::Scram::sha1(username, secret))?,
+            Mechanism::CramMd5         => client.authenticate(sasl::CramMd5::new(username, secret))?,
+            Mechanism::Apop            => client.apop_with_password(username, secret).map(|_| ())?,
+            Mechanism::Plain           => client.authenticate(sasl::Plain::new(username, secret))?,
+            Mechanism::Login           => client.authenticate(sasl::Login::new(username, secret))?,
+            Mechanism::User            => client.login(username, secret)?,
+            Mechanism::OAuthBearer     => client.authenticate(sasl::OAuthBearer::new(username, secret).host(&self.host).port(port))?,
+            Mechanism::XOAuth2         => client.authenticate(sasl::XOAuth2::new(username, secret))?,
+        }
+
+        Ok(client)
+    }
+
+    /// The most preferred authorization method supported by the server and suitable for the credentials
+    #[cfg_attr(not(any(feature = "runtime-tokio", feature = "runtime-sync")), allow(dead_code))]
+    fn choose(&self, capabilities: Option<&Capabilities>, greeting: &str, tls: bool) -> Result<Mechanism> {
+        let token = matches!(self.credentials, Some(Credentials { secret: Secret::Token(_), .. }));
+
+        self.mechanisms
+            .as_deref()
+            .unwrap_or(&Mechanism::DEFAULT)
+            .iter()
+            .copied()
+            .find(|m| m.uses_token() == token && m.is_available(capabilities, greeting, tls))
+            .ok_or(Pop3Error::NoAuthMechanism)
+    }
+}
+
+/// Capabilities of the server, or `None` if it does not support `CAPA`
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+fn optional(capabilities: Result<Capabilities>) -> Result<Option<Capabilities>> {
+    match capabilities {
+        Ok(capabilities) => Ok(Some(capabilities)),
+        Err(Pop3Error::Server { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    use crate::Response;
+
+    fn capa(data: &'static str) -> Capabilities {
+        Capabilities::parse(&Response::new(Bytes::from_static(data.as_bytes()))).unwrap()
+    }
+
+    #[test]
+    fn choose_preferred() {
+        let mut builder = Builder::new("localhost");
+        builder.password("user", "pass");
+
+        let capabilities = capa("\r\nUSER\r\nSASL PLAIN SCRAM-SHA-256 SCRAM-SHA-256-PLUS\r\n");
+
+        assert_eq!(builder.choose(Some(&capabilities), "ready", true).unwrap(), Mechanism::ScramSha256Plus);
+        assert_eq!(builder.choose(Some(&capabilities), "ready", false).unwrap(), Mechanism::ScramSha256);
+
+        builder.mechanisms(&[Mechanism::CramMd5, Mechanism::Plain]);
+        assert_eq!(builder.choose(Some(&capabilities), "ready", false).unwrap(), Mechanism::Plain);
+    }
+
+    #[test]
+    fn choose_without_capa() {
+        let mut builder = Builder::new("localhost");
+        builder.password("user", "pass");
+
+        assert_eq!(builder.choose(None, "ready <1896.697170952@dbc.mtview.ca.us>", false).unwrap(), Mechanism::Apop);
+        assert_eq!(builder.choose(None, "ready", false).unwrap(), Mechanism::User);
+    }
+
+    #[test]
+    fn choose_token() {
+        let mut builder = Builder::new("localhost");
+        builder.oauth("user", "token");
+
+        let capabilities = capa("\r\nUSER\r\nSASL PLAIN XOAUTH2\r\n");
+        assert_eq!(builder.choose(Some(&capabilities), "ready", true).unwrap(), Mechanism::XOAuth2);
+
+        let capabilities = capa("\r\nUSER\r\nSASL PLAIN\r\n");
+        assert!(matches!(builder.choose(Some(&capabilities), "ready", true), Err(Pop3Error::NoAuthMechanism)));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{Pop3Error, Response};
+
+/// Value of the `EXPIRE` capability: how long the server keeps retrieved messages
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Expire {
+    /// Messages are never deleted by the server
+    Never,
+    /// Messages are deleted after the given number of days
+    Days(u64),
+}
+
+/// Server capabilities as reported by the `CAPA` command ([RFC 2449])
+///
+/// [RFC 2449]: https://tools.ietf.org/html/rfc2449
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    top:            bool,
+    user:           bool,
+    sasl:           Vec<String>,
+    resp_codes:     bool,
+    login_delay:    Option<u64>,
+    pipelining:     bool,
+    expire:         Option<Expire>,
+    uidl:           bool,
+    implementation: Option<String>,
+    stls:           bool,
+    utf8:           bool,
+    lang:           bool,
+    other:          Vec<String>,
+}
+
+impl Capabilities {
+    /// Parse the body of a `CAPA` response, the first line being the text of the `+OK` status line
+    pub fn parse(response: &Response) -> Result<Self, Pop3Error> {
+        let text = response.to_string()?;
+        let mut capabilities = Self::default();
+
+        for line in text.lines().skip(1) {
+            let mut words = line.split_whitespace();
+
+            let tag = match words.next() {
+                Some(tag) => tag.to_ascii_uppercase(),
+                None      => continue,
+            };
+
+            match tag.as_str() {
+                "TOP"            => capabilities.top = true,
+                "USER"           => capabilities.user = true,
+                "SASL"           => capabilities.sasl = words.map(|m| m.to_ascii_uppercase()).collect(),
+                "RESP-CODES"     => capabilities.resp_codes = true,
+                // The value may be followed by `USER`, when it depends on the user, which is only known once authorized
+                "LOGIN-DELAY"    => match parse_number(words.next()) {
+                    Some(delay) => capabilities.login_delay = Some(delay),
+                    None        => capabilities.other.push(line.trim().to_string()),
+                },
+                "PIPELINING"     => capabilities.pipelining = true,
+                "EXPIRE"         => match words.next() {
+                    Some(v) if v.eq_ignore_ascii_case("NEVER") => capabilities.expire = Some(Expire::Never),
+                    v => match parse_number(v) {
+                        Some(days) => capabilities.expire = Some(Expire::Days(days)),
+                        None       => capabilities.other.push(line.trim().to_string()),
+                    },
+                },
+                "UIDL"           => capabilities.uidl = true,
+                "IMPLEMENTATION" => capabilities.implementation = Some(
+                    line.trim()[tag.len()..].trim().to_string()
+                ),
+                "STLS"           => capabilities.stls = true,
+                "UTF8"           => capabilities.utf8 = true,
+                "LANG"           => capabilities.lang = true,
+                _                => capabilities.other.push(line.trim().to_string()),
+            }
+        }
+
+        Ok(capabilities)
+    }
+
+    /// `TOP` command is supported
+    pub fn supports_top(&self) -> bool {
+        self.top
+    }
+
+    /// `USER`/`PASS` authorization is supported
+    pub fn supports_user(&self) -> bool {
+        self.user
+    }
+
+    /// `AUTH` command is supported with at least one SASL mechanism
+    pub fn supports_sasl(&self) -> bool {
+        !self.sasl.is_empty()
+    }
+
+    /// SASL mechanisms accepted by the `AUTH` command, in upper case
+    pub fn sasl_mechanisms(&self) -> &[String] {
+        &self.sasl
+    }
+
+    /// The given SASL mechanism is accepted by the `AUTH` command
+    pub fn supports_sasl_mechanism(&self, mechanism: &str) -> bool {
+        self.sasl.iter().any(|m| m.eq_ignore_ascii_case(mechanism))
+    }
+
+    /// Extended response codes ([RFC 2449] section 8) are used in error responses
+    ///
+    /// [RFC 2449]: https://tools.ietf.org/html/rfc2449
+    pub fn supports_resp_codes(&self) -> bool {
+        self.resp_codes
+    }
+
+    /// Minimum number of seconds between logins
+    pub fn login_delay(&self) -> Option<u64> {
+        self.login_delay
+    }
+
+    /// Several commands can be sent without waiting for the responses
+    pub fn supports_pipelining(&self) -> bool {
+        self.pipelining
+    }
+
+    /// Retention policy for the retrieved messages
+    pub fn expire(&self) -> Option<Expire> {
+        self.expire
+    }
+
+    /// `UIDL` command is supported
+    pub fn supports_uidl(&self) -> bool {
+        self.uidl
+    }
+
+    /// Free-form description of the server implementation
+    pub fn implementation(&self) -> Option<&str> {
+        self.implementation.as_deref()
+    }
+
+    /// `STLS` command is supported
+    pub fn supports_stls(&self) -> bool {
+        self.stls
+    }
+
+    /// `UTF8` command is supported ([RFC 6856])
+    ///
+    /// [RFC 6856]: https://tools.ietf.org/html/rfc6856
+    pub fn supports_utf8(&self) -> bool {
+        self.utf8
+    }
+
+    /// `LANG` command is supported ([RFC 6856])
+    ///
+    /// [RFC 6856]: https://tools.ietf.org/html/rfc6856
+    pub fn supports_lang(&self) -> bool {
+        self.lang
+    }
+
+    /// Capability lines not known to this crate, or with a malformed argument, as sent by the server
+    pub fn other(&self) -> &[String] {
+        &self.other
+    }
+}
+
+/// A numeric argument, `None` if it is missing or malformed
+fn parse_number(value: Option<&str>) -> Option<u64> {
+    value?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    fn parse(data: &'static str) -> Capabilities {
+        Capabilities::parse(&Response::new(Bytes::from_static(data.as_bytes()))).unwrap()
+    }
+
+    #[test]
+    fn parse_full() {
+        let capa = parse(
+            "Capability list follows\r\n\
+             TOP\r\n\
+             USER\r\n\
+             SASL PLAIN login XOAUTH2\r\n\
+             RESP-CODES\r\n\
+             LOGIN-DELAY 900\r\n\
+             PIPELINING\r\n\
+             EXPIRE 60 USER\r\n\
+             UIDL\r\n\
+             IMPLEMENTATION Shlemazle Plotz v302\r\n\
+             STLS\r\n\
+             UTF8 USER\r\n\
+             LANG\r\n\
+             X-EXPERIMENTAL foo\r\n"
+        );
+
+        assert!(capa.supports_top());
+        assert!(capa.supports_user());
+        assert_eq!(capa.sasl_mechanisms(), ["PLAIN", "LOGIN", "XOAUTH2"]);
+        assert!(capa.supports_sasl_mechanism("login"));
+        assert!(!capa.supports_sasl_mechanism("CRAM-MD5"));
+        assert!(capa.supports_resp_codes());
+        assert_eq!(capa.login_delay(), Some(900));
+        assert!(capa.supports_pipelining());
+        assert_eq!(capa.expire(), Some(Expire::Days(60)));
+        assert!(capa.supports_uidl());
+        assert_eq!(capa.implementation(), Some("Shlemazle Plotz v302"));
+        assert!(capa.supports_stls());
+        assert!(capa.supports_utf8());
+        assert!(capa.supports_lang());
+        assert_eq!(capa.other(), ["X-EXPERIMENTAL foo"]);
+    }
+
+    #[test]
+    fn parse_minimal() {
+        let capa = parse("\r\nuser\r\nexpire never\r\n");
+
+        assert!(capa.supports_user());
+        assert!(!capa.supports_uidl());
+        assert!(!capa.supports_sasl());
+        assert_eq!(capa.expire(), Some(Expire::Never));
+        assert_eq!(capa.login_delay(), None);
+    }
+
+    #[test]
+    fn parse_invalid_number() {
+        let capa = parse("\r\nLOGIN-DELAY soon\r\nEXPIRE\r\nUIDL\r\n");
+
+        assert_eq!(capa.login_delay(), None);
+        assert_eq!(capa.expire(), None);
+        assert!(capa.supports_uidl());
+        assert_eq!(capa.other(), ["LOGIN-DELAY soon", "EXPIRE"]);
+    }
+
+    #[test]
+    fn parse_per_user() {
+        let capa = parse("\r\nLOGIN-DELAY 900 USER\r\nEXPIRE 30 USER\r\n");
+
+        assert_eq!(capa.login_delay(), Some(900));
+        assert_eq!(capa.expire(), Some(Expire::Days(30)));
+    }
+}
@@ -1,4 +1,19 @@
 use crate::{Command, Response, Pop3Error};
+use crate::protocol::{Protocol, MAX_LINE, MAX_RESPONSE};
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+use {
+    crate::{sasl, Capabilities, SaslMechanism},
+    crate::sasl::ChannelBinding,
+    crate::listing::{parse_listing, parse_single},
+    crate::{Headers, ListEntry, UidlEntry},
+    crate::protocol::{Chunk, Event},
+};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
 
 pub type Result<T> = std::result::Result<T, Pop3Error>;
 
@@ -11,24 +26,357 @@ mod tokio;
 
 
 #[cfg(feature = "runtime-sync")]
-pub use sync::SyncClient;
+pub use sync::{SyncClient, SyncStream};
 
 
 #[cfg(feature = "runtime-tokio")]
-pub use tokio::AsyncClient;
+pub use tokio::{AsyncClient, AsyncStream, RetrStream};
 
-fn join_bytes(arrays: &[&[u8]], separator: u8) -> Vec<u8> {
-    let cap: usize = arrays.iter().map(|a| a.len()).sum();
+/// Time limits of a session, as configured through the [`Builder`](crate::Builder)
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read:    Option<Duration>,
+    pub write:   Option<Duration>,
+    pub session: Option<Duration>,
+}
 
-    let mut result = Vec::with_capacity(cap + arrays.len() - 1);
+/// Limits of a running session: the configured timeouts and the deadline they are cut short by, and the reply sizes
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limits {
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    pub max_line:     usize,
+    pub max_response: usize,
+}
 
-    for (i, array) in arrays.iter().enumerate() {
-        result.extend_from_slice(array);
-        if i < arrays.len() - 1 {
-            result.push(separator);
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            timeouts:     Timeouts::default(),
+            deadline:     None,
+            max_line:     MAX_LINE,
+            max_response: MAX_RESPONSE,
         }
     }
+}
+
+impl Limits {
+    /// Start the session clock
+    pub fn start(timeouts: Timeouts) -> Self {
+        Self {
+            timeouts,
+            deadline: timeouts.session.map(|session| Instant::now() + session),
+            ..Self::default()
+        }
+    }
+
+    /// Protocol enforcing the reply size limits
+    fn protocol(&self) -> Protocol {
+        Protocol::new(self.max_line, self.max_response)
+    }
+
+    /// How long establishing the connection may take
+    pub fn connect(&self) -> Result<Option<Duration>> {
+        self.remaining(self.timeouts.connect, "connect")
+    }
 
-    result
+    /// How long the next read, awaiting the reply to `command`, may block
+    pub fn read(&self, command: &'static str) -> Result<Option<Duration>> {
+        self.remaining(self.timeouts.read, command)
+    }
+
+    /// How long writing `command` may block
+    pub fn write(&self, command: &'static str) -> Result<Option<Duration>> {
+        self.remaining(self.timeouts.write, command)
+    }
+
+    /// The given timeout, cut short by the session deadline, or an error if the deadline has passed
+    fn remaining(&self, timeout: Option<Duration>, command: &'static str) -> Result<Option<Duration>> {
+        let left = match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Some(left),
+                _ => return Err(Pop3Error::Timeout { command }),
+            },
+            None => None,
+        };
+
+        Ok(match (timeout, left) {
+            (Some(timeout), Some(left)) => Some(timeout.min(left)),
+            (timeout, left)             => timeout.or(left),
+        })
+    }
 }
 
+/// Extract the `<...@...>` timestamp banner an APOP capable server puts into its greeting
+pub(crate) fn apop_timestamp(greeting: &str) -> Option<&str> {
+    let start = greeting.find('<')?;
+    let end   = start + greeting[start..].find('>')?;
+
+    let timestamp = &greeting[start..=end];
+
+    timestamp.contains('@').then_some(timestamp)
+}
+
+/// Compute the APOP digest: MD5 of the greeting timestamp followed by the shared secret
+fn apop_digest(greeting: &str, password: &str) -> Result<String> {
+    use md5::{Digest, Md5};
+
+    let timestamp = apop_timestamp(greeting)
+        .ok_or(Pop3Error::NoApopTimestamp)?;
+
+    let digest = Md5::new()
+        .chain_update(timestamp)
+        .chain_update(password)
+        .finalize();
+
+    Ok(digest.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Parse the `STAT` reply: number of messages and their size in octets
+fn parse_stat(response: &Response) -> Result<(u64, u64)> {
+    let stat = response.to_string()?;
+
+    let mut s = stat
+        .trim()
+        .split(' ')
+        .map(|i| i.parse::<u64>().map_err(Pop3Error::InvalidNumber));
+
+    Ok((
+        s.next().ok_or(Pop3Error::InvalidResponse)??,
+        s.next().ok_or(Pop3Error::InvalidResponse)??,
+    ))
+}
+
+/// The message of a `RETR` reply, that is everything after the status line
+fn message_body(response: Response) -> Bytes {
+    let data = response.raw();
+
+    match data.iter().position(|&b| b == b'\n') {
+        Some(pos) => data.slice(pos + 1..),
+        None      => Bytes::new(),
+    }
+}
+
+/// Most commands in flight while pipelining, so that the requests always fit in the socket buffers
+const PIPELINE_WINDOW: usize = 32;
+
+/// Progress of a batch of pipelined commands ([RFC 2449] section 6.6), independent of the runtime driving it
+///
+/// Commands are sent in groups, topping the window up once half of it has been answered.
+/// With a window of one, this is plain lock-step.
+///
+/// [RFC 2449]: https://tools.ietf.org/html/rfc2449#section-6.6
+struct Pipeline<'c, 'a> {
+    commands:  &'c [Command<'a>],
+    results:   Vec<Option<Result<Response>>>,
+    in_flight: VecDeque<usize>,
+    next:      usize,
+    window:    usize,
+}
+
+impl<'c, 'a> Pipeline<'c, 'a> {
+    fn new(commands: &'c [Command<'a>], pipelining: bool) -> Self {
+        Self {
+            commands,
+            results:   commands.iter().map(|_| None).collect(),
+            in_flight: VecDeque::new(),
+            next:      0,
+            window:    if pipelining { PIPELINE_WINDOW } else { 1 },
+        }
+    }
+
+    /// Encode the next commands if the window is at most half full, returning the keyword of the first one and the request to write
+    fn refill(&mut self, protocol: &mut Protocol) -> Option<(&'static str, Vec<u8>)> {
+        if self.in_flight.len() > self.window / 2 {
+            return None;
+        }
+
+        let mut batch: Option<(&'static str, Vec<u8>)> = None;
+
+        while self.next < self.commands.len() && self.in_flight.len() < self.window {
+            let cmd = &self.commands[self.next];
+
+            if cmd.is_pipelinable() {
+                let request = protocol.encode(cmd);
+
+                match &mut batch {
+                    Some((_, data)) => data.extend_from_slice(&request),
+                    None            => batch = Some((cmd.name(), request)),
+                }
+                self.in_flight.push_back(self.next);
+            } else {
+                self.results[self.next] = Some(Err(Pop3Error::NotPipelinable { command: cmd.name() }));
+            }
+
+            self.next += 1;
+        }
+
+        batch
+    }
+
+    /// Whether replies are still awaited
+    fn is_pending(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Record the outcome of the oldest command in flight, returning whether the connection is still usable
+    fn complete(&mut self, result: Result<Response>) -> bool {
+        let usable = !matches!(
+            result,
+            Err(Pop3Error::Io(_) | Pop3Error::Timeout { .. } | Pop3Error::ConnectionClosed | Pop3Error::ResponseTooLarge { .. })
+        );
+
+        if let Some(index) = self.in_flight.pop_front() {
+            self.results[index] = Some(result);
+        }
+
+        usable
+    }
+
+    /// Results in the order of the commands, the unanswered ones failing with [`Pop3Error::ConnectionClosed`]
+    fn finish(self) -> Vec<Result<Response>> {
+        self.results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(Pop3Error::ConnectionClosed)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apop() {
+        let greeting = "POP3 server ready <1896.697170952@dbc.mtview.ca.us>";
+
+        assert_eq!(apop_timestamp(greeting), Some("<1896.697170952@dbc.mtview.ca.us>"));
+        assert_eq!(apop_digest(greeting, "tanstaaf").unwrap(), "c4c9334bac560ecc979e58001b3e22fb");
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits::default();
+        assert_eq!(limits.read("NOOP").unwrap(), None);
+
+        let limits = Limits::start(Timeouts { read: Some(Duration::from_secs(5)), ..Default::default() });
+        assert_eq!(limits.read("NOOP").unwrap(), Some(Duration::from_secs(5)));
+        assert_eq!(limits.write("NOOP").unwrap(), None);
+
+        let limits = Limits::start(Timeouts {
+            read:    Some(Duration::from_secs(5)),
+            session: Some(Duration::from_secs(1)),
+            ..Default::default()
+        });
+        assert!(limits.read("NOOP").unwrap().unwrap() <= Duration::from_secs(1));
+        assert!(limits.connect().unwrap().unwrap() <= Duration::from_secs(1));
+
+        let limits = Limits::start(Timeouts { session: Some(Duration::ZERO), ..Default::default() });
+        assert!(matches!(limits.read("NOOP"), Err(Pop3Error::Timeout { command: "NOOP" })));
+    }
+
+    #[test]
+    fn apop_no_timestamp() {
+        assert_eq!(apop_timestamp("POP3 server ready"), None);
+        assert_eq!(apop_timestamp("POP3 server <ready>"), None);
+        assert_eq!(apop_timestamp("POP3 server <ready@"), None);
+        assert!(matches!(apop_digest("POP3 server ready", "tanstaaf"), Err(Pop3Error::NoApopTimestamp)));
+    }
+
+    #[test]
+    fn pipeline_window() {
+        let commands: Vec<_> = (1..=40).map(|id| Command::Dele { id }).collect();
+        let mut protocol = Protocol::default();
+        let mut pipeline = Pipeline::new(&commands, true);
+
+        let (command, request) = pipeline.refill(&mut protocol).unwrap();
+        assert_eq!(command, "DELE");
+        assert_eq!(request.iter().filter(|&&b| b == b'\n').count(), PIPELINE_WINDOW);
+
+        // Topped up only once half of the window has been answered
+        for _ in 0..PIPELINE_WINDOW / 2 - 1 {
+            assert!(pipeline.complete(Ok(Response::new(Bytes::new()))));
+            assert!(pipeline.refill(&mut protocol).is_none());
+        }
+        assert!(pipeline.complete(Ok(Response::new(Bytes::new()))));
+        assert!(pipeline.refill(&mut protocol).is_some());
+        assert_eq!(pipeline.in_flight.len(), PIPELINE_WINDOW / 2 + 8);
+
+        assert!(!pipeline.complete(Err(Pop3Error::ConnectionClosed)));
+
+        let results = pipeline.finish();
+        assert_eq!(results.len(), 40);
+        assert!(results[..16].iter().all(Result::is_ok));
+        assert!(results[16..].iter().all(|r| matches!(r, Err(Pop3Error::ConnectionClosed))));
+    }
+
+    #[test]
+    fn pipeline_lock_step() {
+        let commands = [Command::Stat, Command::Quit, Command::Noop];
+        let mut protocol = Protocol::default();
+        let mut pipeline = Pipeline::new(&commands, false);
+
+        assert_eq!(pipeline.refill(&mut protocol).unwrap(), ("STAT", b"STAT\r\n".to_vec()));
+        assert!(pipeline.refill(&mut protocol).is_none());
+        assert!(pipeline.complete(Ok(Response::new(Bytes::new()))));
+
+        // QUIT is skipped without ending the batch
+        assert_eq!(pipeline.refill(&mut protocol).unwrap(), ("NOOP", b"NOOP\r\n".to_vec()));
+        assert!(pipeline.complete(Err(Pop3Error::server("[SYS/TEMP] busy"))));
+        assert!(!pipeline.is_pending());
+
+        let results = pipeline.finish();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Pop3Error::NotPipelinable { command: "QUIT" })));
+        assert!(matches!(results[2], Err(Pop3Error::Server { .. })));
+    }
+
+    /// Replies scripted in advance, and the requests written
+    #[cfg(feature = "runtime-sync")]
+    struct Script {
+        replies:  std::io::Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    #[cfg(feature = "runtime-sync")]
+    impl std::io::Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    #[cfg(feature = "runtime-sync")]
+    impl std::io::Write for Script {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.requests.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "runtime-sync")]
+    #[test]
+    fn scram_bad_server_signature() {
+        use crate::sasl::{Scram, ScramHash};
+
+        let server_first = "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        let server_final = "v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+        // The server accepts the exchange cancelled because of its wrong signature
+        let replies = format!(
+            "+OK POP3 server ready\r\n+ {}\r\n+ {}\r\n+OK maildrop locked and ready\r\n+OK\r\n",
+            sasl::encode(server_first.as_bytes()),
+            sasl::encode(server_final.as_bytes()),
+        );
+
+        let script = Script { replies: std::io::Cursor::new(replies.into_bytes()), requests: Vec::new() };
+        let mut client = crate::SyncClient::from_stream(script).unwrap();
+
+        let scram = Scram::with_nonce(ScramHash::Sha256, "user", "pencil", "rOprNGfwEbeRWgbNEkqO".into());
+        assert!(matches!(client.authenticate(scram), Err(Pop3Error::Sasl(_))));
+        assert!(matches!(client.noop(), Err(Pop3Error::ConnectionClosed)));
+    }
+}
@@ -1,12 +1,117 @@
 use super::*;
 
-use std::io::BufRead;
-use std::io::{BufReader, Write};
-use std::net::TcpStream;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
+use bytes::Bytes;
 
 
-use bytes::{Bytes, BytesMut, Buf, BufMut};
+#[cfg(feature = "with-rustls")]
+use {
+    rustls::{ClientConfig, ClientConnection, StreamOwned},
+    rustls::pki_types::ServerName,
+    std::sync::Arc,
+};
+
+#[cfg(feature = "with-rustls")]
+use crate::{Builder, Security};
+
+/// Default transport of [`SyncClient`]: either a plain TCP socket or a TLS session over it
+///
+/// Any other stream can be used through [`SyncClient::from_stream`].
+pub enum SyncStream {
+    /// Plaintext TCP connection
+    Plain(TcpStream),
+    /// TLS session over a TCP connection
+    #[cfg(feature = "with-rustls")]
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+    /// The transport has been taken away, e.g. by a failed STLS upgrade
+    #[cfg(feature = "with-rustls")]
+    Closed,
+}
+
+#[cfg(feature = "with-rustls")]
+fn closed<T>() -> std::io::Result<T> {
+    Err(std::io::ErrorKind::NotConnected.into())
+}
+
+impl Read for SyncStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.read(buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => s.read(buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+}
+
+impl Write for SyncStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.write(buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => s.write(buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(s) => s.flush(),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => s.flush(),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+}
+
+#[cfg(feature = "with-rustls")]
+impl SyncStream {
+    /// Channel binding data of the TLS connection, `tls-exporter` type ([RFC 9266])
+    ///
+    /// [RFC 9266]: https://tools.ietf.org/html/rfc9266
+    fn channel_binding(&self) -> Option<ChannelBinding> {
+        match self {
+            Self::Tls(s) => s.conn
+                .export_keying_material(vec![0u8; 32], b"EXPORTER-Channel-Binding", None)
+                .ok()
+                .map(|data| ChannelBinding { kind: "tls-exporter", data }),
+            _ => None,
+        }
+    }
+}
+
+/// Connect to the first reachable address of the host within the connect time limit
+fn tcp_connect(host: &str, port: u16, limits: &Limits) -> Result<TcpStream> {
+    let Some(timeout) = limits.connect()? else {
+        return TcpStream::connect((host, port))
+            .map_err(Pop3Error::Io);
+    };
+
+    let mut error = std::io::Error::new(ErrorKind::InvalidInput, "could not resolve to any addresses");
+
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e)     => error = e,
+        }
+    }
+
+    Err(timed_out(error, "connect"))
+}
+
+/// Report an expired socket timeout as [`Pop3Error::Timeout`]
+fn timed_out(error: std::io::Error, command: &'static str) -> Pop3Error {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Pop3Error::Timeout { command },
+        _ => Pop3Error::Io(error),
+    }
+}
 
 /// The key structure for the crate, delineating capabilities of the POP3 client as per the protocol [RFC]
 ///
@@ -23,36 +128,226 @@ use bytes::{Bytes, BytesMut, Buf, BufMut};
 /// To find out more, read the output of the error you've got -- it's always a string!
 ///
 /// [RFC]: https://tools.ietf.org/html/rfc1081
-pub struct SyncClient {
-    #[cfg(feature = "with-rustls")]
-    client: BufReader<StreamOwned<ClientSession, TcpStream>>,
-    #[cfg(not(feature = "with-rustls"))]
-    client: BufReader<TcpStream>,
+pub struct SyncClient<S = SyncStream> {
+    stream: S,
+    protocol: Protocol,
+    #[cfg_attr(not(feature = "with-rustls"), allow(dead_code))]
+    host: String,
+    greeting: String,
     authorized: bool,
+    capabilities: Option<Capabilities>,
+    channel_binding: Option<ChannelBinding>,
+    limits: Limits,
+    /// Handle of the TCP socket under the transport, to apply the time limits to
+    socket: Option<TcpStream>,
 }
 
 impl SyncClient {
+    /// Connect to given host and port.
+    ///
+    /// This is the simplest way to initiate connection, so it's preferable to use it in a straightforward manner unless you have specific [`ClientConfig`] reservations.
+    ///
+    /// # Example
+    ///
     /// ```no_run
     /// # use std::result::Result;
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    ///let client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    ///let client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     ///
     /// #    Ok(())
     /// # }
     /// ```
     ///
-    /// [`ClientConfig`]: https://docs.rs/rustls/0.15.2/rustls/struct.ClientConfig.html
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
     pub fn connect(host: &str, port: u16) -> Result<Self> {
-        let mut client = TcpStream::connect((host, port))
-            .map(|client| Self {
-                client: BufReader::new(client),
-                authorized: false,
-            })
-            .map_err(Pop3Error::Io)?;
+        Self::connect_limited(host, port, Limits::default())
+    }
 
-        client.read_response(false)?;
+    pub(crate) fn connect_limited(host: &str, port: u16, limits: Limits) -> Result<Self> {
+        let stream = tcp_connect(host, port, &limits)?;
+        let socket = stream.try_clone()?;
+
+        Self::from_transport(host, SyncStream::Plain(stream), limits, Some(socket))
+    }
+
+    /// Connect to given host and port over implicit TLS (POP3S, usually port 995).
+    ///
+    /// Server certificates are verified against the [webpki-roots] trust anchors. Use [`Builder::rustls_config`] to supply a custom [`ClientConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    ///let client = SyncClient::connect_tls("pop.gmail.com", 995)?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [webpki-roots]: https://docs.rs/webpki-roots
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
+    #[cfg(feature = "with-rustls")]
+    pub fn connect_tls(host: &str, port: u16) -> Result<Self> {
+        Builder::new(host)
+            .port(port)
+            .security(Security::Tls)
+            .connect_sync()
+    }
+
+    #[cfg(feature = "with-rustls")]
+    pub(crate) fn connect_rustls(host: &str, port: u16, config: Arc<ClientConfig>, limits: Limits) -> Result<Self> {
+        let hostname = ServerName::try_from(host.to_string())
+            .map_err(|_| Pop3Error::InvalidDnsName(host.into()))?;
+
+        let stream = tcp_connect(host, port, &limits)?;
+        let socket = stream.try_clone()?;
+
+        let session = ClientConnection::new(config, hostname)?;
+        let mut stream = StreamOwned::new(session, stream);
+
+        // The TLS handshake is a part of establishing the connection
+        let limit = limits.connect()?;
+        socket.set_read_timeout(limit)?;
+        socket.set_write_timeout(limit)?;
+
+        stream.conn
+            .complete_io(&mut stream.sock)
+            .map_err(|e| timed_out(e, "connect"))?;
+
+        socket.set_read_timeout(None)?;
+        socket.set_write_timeout(None)?;
+
+        let mut client = Self::from_transport(host, SyncStream::Tls(Box::new(stream)), limits, Some(socket))?;
+
+        client.channel_binding = client.stream.channel_binding();
+
+        Ok(client)
+    }
+
+    /// Upgrade the plaintext connection to TLS using the `STLS` command ([RFC 2595])
+    ///
+    /// Server certificates are verified against the [webpki-roots] trust anchors for the host the client was connected to. Use a [`Builder`] with [`Security::StlsRequired`] to supply a custom [`ClientConfig`].
+    ///
+    /// Any data the server sent after the `+OK` response and before the TLS handshake is discarded, so it can not be injected into the protected session.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// client.stls()?;
+    /// client.login("sweet_username", "very_secret_password")?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::AlreadyAuthenticated`] if called after a successful authorization, as STLS is only allowed in the AUTHORIZATION state
+    /// - [`Pop3Error::TlsAlreadyActive`] if the connection is already encrypted
+    /// - The server may return an error response if it does not support `STLS`
+    ///
+    /// [RFC 2595]: https://tools.ietf.org/html/rfc2595
+    /// [webpki-roots]: https://docs.rs/webpki-roots
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
+    #[cfg(feature = "with-rustls")]
+    pub fn stls(&mut self) -> Result<()> {
+        let config = Builder::default().tls_config();
+        self.stls_rustls(config)
+    }
+
+    #[cfg(feature = "with-rustls")]
+    pub(crate) fn stls_rustls(&mut self, config: Arc<ClientConfig>) -> Result<()> {
+        if self.authorized {
+            return Err(Pop3Error::AlreadyAuthenticated);
+        }
+
+        if matches!(self.stream, SyncStream::Tls(_)) {
+            return Err(Pop3Error::TlsAlreadyActive);
+        }
+
+        let hostname = ServerName::try_from(self.host.clone())
+            .map_err(|_| Pop3Error::InvalidDnsName(self.host.clone()))?;
+
+        self.request(&Command::Stls)?;
+        self.capabilities = None;
+
+        // Anything received after the response is discarded
+        self.protocol.discard();
+
+        let stream = match std::mem::replace(&mut self.stream, SyncStream::Closed) {
+            SyncStream::Plain(stream) => stream,
+            _ => return Err(Pop3Error::ConnectionClosed),
+        };
+
+        let session = ClientConnection::new(config, hostname)?;
+        let mut stream = StreamOwned::new(session, stream);
+
+        let limit = self.limits.read("STLS")?;
+        self.limit(TcpStream::set_read_timeout, limit)?;
+        self.limit(TcpStream::set_write_timeout, limit)?;
+
+        // Complete the handshake now, so certificate problems are reported by this call
+        stream.conn
+            .complete_io(&mut stream.sock)
+            .map_err(|e| timed_out(e, "STLS"))?;
+
+        self.stream = SyncStream::Tls(Box::new(stream));
+        self.channel_binding = self.stream.channel_binding();
+
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> SyncClient<S> {
+    /// Start a session over an already connected stream, reading the server greeting.
+    ///
+    /// This allows to run over Unix sockets, tunnels, in-memory pipes or a TLS stack the crate does not ship.
+    /// `STLS`, channel binding and timeouts are only available over the default transport.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// // Local end of an SSH tunnel
+    /// let stream = std::net::TcpStream::connect("127.0.0.1:1100")?;
+    /// let client = SyncClient::from_stream(stream)?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn from_stream(stream: S) -> Result<Self> {
+        Self::from_transport("", stream, Limits::default(), None)
+    }
+
+    fn from_transport(host: &str, stream: S, limits: Limits, socket: Option<TcpStream>) -> Result<Self> {
+        let mut client = Self {
+            stream,
+            protocol: limits.protocol(),
+            host: host.into(),
+            greeting: String::new(),
+            authorized: false,
+            capabilities: None,
+            channel_binding: None,
+            limits,
+            socket,
+        };
+
+        let greeting = client.request(&Command::Greet)?;
+
+        client.greeting = String::from_utf8_lossy(greeting.raw())
+            .trim_end()
+            .to_string();
 
         Ok(client)
     }
@@ -63,10 +358,10 @@ impl SyncClient {
     ///
     /// ```no_run
     /// # use std::result::Result;
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// client.login("sweet_username", "very_secret_password")?;
     /// #    Ok(())
     /// # }
@@ -86,7 +381,7 @@ impl SyncClient {
 
             .map(|_| {
                 self.authorized = true;
-                ()
+                self.capabilities = None;
             })
     }
 
@@ -97,10 +392,10 @@ impl SyncClient {
     /// ```compile_fail
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     ///client.quit()?;
     ///client.noop()?; // Shouldn't compile, as the client has been consumed upon quitting
     /// #    Ok(())
@@ -121,10 +416,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// let (messages, octets) = client.stat()?;
     /// assert_eq!(messages, 2);
     /// assert_eq!(octets, 340);
@@ -132,19 +427,8 @@ impl SyncClient {
     /// # }
     /// ```
     pub fn stat(&mut self) -> Result<(u64, u64)> {
-
-        let stat = self.request(&Command::Stat)
-            .and_then(|r| r.to_string())?;
-
-        let mut s = stat
-            .trim()
-            .split(' ')
-            .map(|i| i.parse::<u64>().map_err(Pop3Error::InvalidNumber));
-
-        Ok((
-            s.next().ok_or(Pop3Error::InvalidResponse)??,
-            s.next().ok_or(Pop3Error::InvalidResponse)??,
-        ))
+        self.request(&Command::Stat)
+            .and_then(|r| parse_stat(&r))
     }
 
     /// Show the statistical information on a chosen letter, or all letters. The information in question always required to start with the letter size, but use of additional stats is not regimented in any way.
@@ -155,10 +439,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// let single_stats = client.list(Some(1))?; // show info on the letter number 1
     /// let all_stats = client.list(None)?; // show info on all letters
     ///
@@ -173,6 +457,61 @@ impl SyncClient {
         self.request(&Command::List { id })
     }
 
+    /// Show the size of every message in the mailbox, see [`list`](Self::list)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// for entry in client.list_all()? {
+    ///     println!("message {} is {} octets", entry.id, entry.size);
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// [`Pop3Error::MalformedLine`] if a line of the listing does not follow the [RFC] grammar
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        self.list(None)
+            .and_then(|r| parse_listing(&r, ListEntry::parse))
+    }
+
+    /// Show the size of the chosen message, see [`list`](Self::list)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let entry = client.list_one(1)?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::MalformedLine`] if the response does not follow the [RFC] grammar
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        self.list(Some(id))
+            .and_then(|r| parse_single(&r, ListEntry::parse))
+    }
+
     /// Show the full content of the chosen message
     ///
     ///
@@ -181,10 +520,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// let letter_content = client.retr(5)?;
     ///
     /// #    Ok(())
@@ -196,18 +535,84 @@ impl SyncClient {
     /// - The letter under the given index has been marked deleted
     pub fn retr(&mut self, id: u64) -> Result<Bytes> {
         self.request(&Command::Retr { id })
+            .map(message_body)
+    }
 
-            .map(|s| {
-                let tmp = join_bytes(
-                    &s.raw()[..]
-                        .split(|&b| b == b'\n')
-                        .skip(1)
-                        .collect::<Vec<&[u8]>>(),
-                    b'\n'
-                );
+    /// Show the full content of the chosen message, unless it exceeds `max_size` octets, see [`retr`](Self::retr)
+    ///
+    /// Overrides the maximum response size of the session, e.g. to accept a message known to be large from [`list_one`](Self::list_one).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let letter_content = client.retr_with_limit(5, 512 << 20)?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::ResponseTooLarge`] if the message exceeds the limit, in which case the session is closed
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    pub fn retr_with_limit(&mut self, id: u64, max_size: usize) -> Result<Bytes> {
+        let cmd = Command::Retr { id };
+        let request = self.protocol.encode_limited(&cmd, max_size);
 
-                Bytes::copy_from_slice(&tmp)
-            })
+        self.write(cmd.name(), &request)?;
+        self.read_response()
+            .map(message_body)
+    }
+
+    /// Write the full content of the chosen message to the writer as it is received, see [`retr`](Self::retr)
+    ///
+    /// Unlike [`retr`](Self::retr), the message is never held in memory as a whole, so it suits large messages.
+    /// Returns the number of octets written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let mut file = std::fs::File::create("message.eml")?;
+    /// client.retr_to_writer(5, &mut file)?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::Io`] if the writer fails, in which case the rest of the message is skipped by the next command
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    pub fn retr_to_writer<W: Write>(&mut self, id: u64, writer: &mut W) -> Result<u64> {
+        let cmd = Command::Retr { id };
+        let request = self.protocol.encode_stream(&cmd);
+
+        self.write(cmd.name(), &request)?;
+        self.read_response()?;
+
+        let mut written = 0;
+
+        loop {
+            match self.read_chunk()? {
+                Chunk::Data(data) => {
+                    writer.write_all(&data).map_err(Pop3Error::Io)?;
+                    written += data.len() as u64;
+                }
+                Chunk::End => return Ok(written),
+            }
+        }
     }
 
 
@@ -219,10 +624,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// client.dele(3)?; // now, the THIRD message is marked as deleted, and no new manipulations on it are possible
     ///
     /// #    Ok(())
@@ -244,10 +649,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// assert!(client.noop().is_ok());
     ///
     /// #    Ok(())
@@ -267,10 +672,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// client.dele(3)?;
     /// client.dele(4)?;
     /// client.rset()?; // undo all the previous deletions
@@ -289,10 +694,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// let top = client.top(1, 2)?; // Get TWO first lines of the FIRST message
     ///
     /// #    Ok(())
@@ -307,6 +712,35 @@ impl SyncClient {
         self.request(&Command::Top { id, lines })
     }
 
+    /// Parse the header section of the chosen message, without downloading its body (`TOP id 0`)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let headers = client.headers(1)?;
+    ///
+    /// if headers.subject().is_some_and(|s| s.contains("invoice")) {
+    ///     let letter_content = client.retr(1)?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// The server may return an error response if:
+    /// - The letter under the given index does not exist in the mailbox
+    /// - The letter under the given index has been marked deleted
+    /// - The server does not support `TOP`
+    pub fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.request(&Command::Top { id, lines: 0 })
+            .map(|r| Headers::parse(&message_body(r)))
+    }
+
     /// Show the unique ID listing for the chosen message or for all the messages. Unlike message numbering, this ID does not change between sessions.
     ///
     ///
@@ -315,10 +749,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// let uidl_all = client.uidl(None)?;
     /// let uidl_one = client.uidl(Some(1))?;
     ///
@@ -334,6 +768,61 @@ impl SyncClient {
         self.request(&Command::Uidl { id })
     }
 
+    /// Show the unique ID of every message in the mailbox, see [`uidl`](Self::uidl)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// for entry in client.uidl_all()? {
+    ///     println!("message {} has id {}", entry.id, entry.uid);
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// [`Pop3Error::MalformedLine`] if a line of the listing does not follow the [RFC] grammar
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        self.uidl(None)
+            .and_then(|r| parse_listing(&r, UidlEntry::parse))
+    }
+
+    /// Show the unique ID of the chosen message, see [`uidl`](Self::uidl)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let entry = client.uidl_one(1)?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::MalformedLine`] if the response does not follow the [RFC] grammar
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub fn uidl_one(&mut self, id: u64) -> Result<UidlEntry> {
+        self.uidl(Some(id))
+            .and_then(|r| parse_single(&r, UidlEntry::parse))
+    }
+
     /// Authorise using the APOP method
     ///
     /// Refer to the POP3 [RFC] for details.
@@ -343,10 +832,10 @@ impl SyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{SyncClient, Pop3Error};
     /// #
-    /// # fn main() -> Result<(), String> {
-    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
     /// client.apop("another_sweet_username", "c4c9334bac560ecc979e58001b3e22fb")?;
     ///
     /// #    Ok(())
@@ -361,121 +850,332 @@ impl SyncClient {
             return Err(Pop3Error::AlreadyAuthenticated);
         }
         self.request(&Command::Apop { id, token })
-            .map(|s| {
+            .inspect(|_| {
                 self.authorized = true;
-                s
+                self.capabilities = None;
             })
     }
 
-    #[cfg(feature = "with-rustls")]
-    fn connect_rustls(host: &str, port: u16, config: Arc<ClientConfig>) -> Result<Self> {
-        let hostname = DNSNameRef::try_from_ascii_str(host).map_err(|_| "DNS_NAMEREF_FAILED")?;
-
-        let session = ClientSession::new(&config, hostname);
-        let socket = TcpStream::connect((host, port))
-            .map(BufReader::new)
-            .map_err(Pop3Error::Io)
-            .and_then(|mut client| {
-                let mut buf = String::new();
-                client
-                    .read_line(&mut buf)
-                    .map_err(|e| e.to_string())
-                    .and_then(|_| {
-                        if buf.starts_with("+OK") {
-                            Ok(buf[4..].to_owned())
-                        } else {
-                            Err(buf[5..].to_owned())
-                        }
-                    })
-                    .map(|_| client)
-            })
-            .and_then(|mut client| {
-                client
-                    .get_mut()
-                    .write_all("STLS\r\n".as_bytes())
-                    .map_err(|e| e.to_string())
-                    .and_then(|_| {
-                        let mut buf = String::new();
-                        client
-                            .read_line(&mut buf)
-                            .map_err(|e| e.to_string())
-                            .and_then(|_| {
-                                println!("STLS: {}", &buf);
-                                if buf.starts_with("+OK") {
-                                    Ok(buf[4..].to_owned())
-                                } else {
-                                    Err(buf[5..].to_owned())
-                                }
-                            })
-                    })
-                    .map(|_| client.into_inner())
-            })?;
-
-        let tls_stream = StreamOwned::new(session, socket);
-
-        Ok(Self {
-            client: BufReader::new(tls_stream),
-            authorized: false,
-        })
+    /// Authorise using the APOP method, computing the digest from the server greeting timestamp and the password
+    ///
+    /// Refer to the POP3 [RFC] for details.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// client.apop_with_password("another_sweet_username", "very_secret_password")?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::NoApopTimestamp`] if the server greeting has no timestamp, that is the server does not support APOP
+    /// - The server will return error if permission was denied.
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939#page-15
+    pub fn apop_with_password(&mut self, id: &str, password: &str) -> Result<Response> {
+        let digest = apop_digest(&self.greeting, password)?;
+
+        self.apop(id, &digest)
     }
 
-    fn read_response(&mut self, multiline: bool) -> Result<Response> {
-        let mut response = BytesMut::new();
-        let mut buffer   = vec![];
+    /// The server greeting, without the `+OK` status
+    pub fn greeting(&self) -> &str {
+        &self.greeting
+    }
 
-        let amount = self.client
-            .read_until(b'\n', &mut buffer)
-            .map_err(Pop3Error::Io)?;
+    /// Whether the session has left the AUTHORIZATION state
+    pub fn is_authorized(&self) -> bool {
+        self.authorized
+    }
 
-        if amount == 0 {
-            return Err(Pop3Error::ConnectionClosed)
+    /// Authorise using a SASL mechanism through the `AUTH` command ([RFC 5034])
+    ///
+    /// Built-in mechanisms live in the [`sasl`] module; any type implementing [`SaslMechanism`] can be used as well.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// use pop3_client::sasl;
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// client.authenticate(sasl::Plain::new("sweet_username", "very_secret_password"))?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::AlreadyAuthenticated`] if the session is already authorized
+    /// - Any error of the mechanism, in which case the exchange is cancelled with `*`.
+    ///   If the server accepted nonetheless, or the mechanism fails once it did, e.g. on a wrong SCRAM server signature, the session is closed.
+    /// - The server will return error if permission was denied or the mechanism is not supported.
+    ///
+    /// [RFC 5034]: https://tools.ietf.org/html/rfc5034
+    /// [`sasl`]: crate::sasl
+    pub fn authenticate<M: SaslMechanism>(&mut self, mut mechanism: M) -> Result<()> {
+        if self.authorized {
+            return Err(Pop3Error::AlreadyAuthenticated);
         }
 
-        if buffer.starts_with(b"+OK") {
-            response.put(&buffer[4..]);
-        } else {
-            let error_msg = std::str::from_utf8(
-                if buffer.len() < 6 { &buffer } else { &buffer[5..] },
-            );
+        mechanism.set_channel_binding(self.channel_binding.clone());
 
-            let err = match error_msg {
-                Ok(v)  => Pop3Error::other(v),
-                Err(e) => Pop3Error::InvalidString(e),
+        let initial = mechanism.initial_response()
+            .map(|r| sasl::encode_initial(&r));
+
+        // Too long initial response is sent in reply to the first, empty, challenge instead
+        let (inline, mut pending) = match initial {
+            Some(r) if sasl::fits_auth_line(mechanism.name(), &r) => (Some(r), None),
+            r => (None, r),
+        };
+
+        self.send(&Command::Auth { mechanism: mechanism.name(), initial: inline.as_deref() })?;
+
+        loop {
+            let challenge = match self.read_challenge() {
+                Ok(Some(challenge)) => challenge,
+                Ok(None) => break,
+                Err(e @ Pop3Error::Server { .. }) => return Err(mechanism.failure(e)),
+                Err(e) => return Err(e),
             };
 
-            return Err(err)
+            let response = match pending.take() {
+                Some(r) => Ok(r),
+                None    => sasl::decode(&challenge)
+                    .and_then(|c| mechanism.respond(&c))
+                    .map(|r| sasl::encode(&r)),
+            };
+
+            match response {
+                Ok(data) => self.send(&Command::AuthResponse { data: &data })?,
+                Err(e) => {
+                    self.send(&Command::AuthResponse { data: "*" })?;
+                    // The server must reject the cancelled exchange, the session can not be trusted otherwise
+                    if !matches!(self.read_event(), Err(Pop3Error::Server { .. })) {
+                        self.protocol.abort();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        // The server accepted, but failed to authenticate itself, e.g. with a wrong SCRAM signature
+        if let Err(e) = mechanism.complete() {
+            self.protocol.abort();
+            return Err(e);
         }
 
-        if multiline {
-            loop {
-                buffer.clear();
+        self.authorized = true;
+        self.capabilities = None;
 
-                let amount = self.client
-                    .read_until(b'\n', &mut buffer)
-                    .map_err(Pop3Error::Io)?;
+        Ok(())
+    }
 
-                if amount == 0 {
-                    return Err(Pop3Error::ConnectionClosed)
-                }
+    /// List the server capabilities (that's what the `CAPA` command does, see [RFC 2449]).
+    ///
+    /// The result is cached, so subsequent calls do not hit the server until the capabilities may change, that is after `STLS` or a successful authorization.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let capabilities = client.capa()?;
+    ///
+    /// if capabilities.supports_uidl() {
+    ///     let uidl_all = client.uidl(None)?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// The server may return an error response if it does not support `CAPA`.
+    ///
+    /// [RFC 2449]: https://tools.ietf.org/html/rfc2449
+    pub fn capa(&mut self) -> Result<Capabilities> {
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
+        }
+
+        let capabilities = self.request(&Command::Capa)
+            .and_then(|r| Capabilities::parse(&r))?;
+
+        self.capabilities = Some(capabilities.clone());
+
+        Ok(capabilities)
+    }
+
+    /// Send a batch of commands and read their replies in order, e.g. to retrieve many messages over a slow link
+    ///
+    /// When the server announces `PIPELINING` ([RFC 2449] section 6.6), the commands are written in groups without waiting for the replies,
+    /// keeping a bounded number of them in flight. Otherwise they are sent one at a time.
+    /// A server rejecting `CAPA` is then taken to announce no capabilities, until they may change.
+    ///
+    /// Commands changing the session state (`USER`, `PASS`, `APOP`, `AUTH`, `STLS`, `QUIT`) are not sent and fail with [`Pop3Error::NotPipelinable`].
+    /// An error response only fails the corresponding command, while the replies after a transport failure fail with [`Pop3Error::ConnectionClosed`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{Command, SyncClient, Pop3Error};
+    /// #
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// let commands: Vec<_> = (1..=100).map(|id| Command::Retr { id }).collect();
+    ///
+    /// for reply in client.pipeline(&commands) {
+    ///     let message = reply?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [RFC 2449]: https://tools.ietf.org/html/rfc2449#section-6.6
+    pub fn pipeline(&mut self, commands: &[Command<'_>]) -> Vec<Result<Response>> {
+        let pipelining = match self.capa() {
+            Ok(capabilities) => capabilities.supports_pipelining(),
+            Err(Pop3Error::Server { .. }) => {
+                // A server without CAPA announces nothing, and is not asked again before the next batch
+                self.capabilities = Some(Capabilities::default());
+                false
+            }
+            Err(_) => false,
+        };
+        let mut pipeline = Pipeline::new(commands, pipelining);
 
-                if buffer == b".\r\n" {
+        loop {
+            if let Some((command, request)) = pipeline.refill(&mut self.protocol) {
+                if let Err(e) = self.write(command, &request) {
+                    pipeline.complete(Err(e));
+                    self.protocol.abort();
                     break;
                 }
+            }
+
+            if !pipeline.is_pending() {
+                break;
+            }
 
-                response.put(&buffer[..]);
+            // The commands left in flight would take the replies to the next ones
+            if !pipeline.complete(self.read_response()) {
+                self.protocol.abort();
+                break;
             }
         }
 
-        Ok(Response::new(response.freeze()))
+        pipeline.finish()
     }
 
-    fn request(&mut self, cmd: &Command<'_>) -> Result<Response> {
-        self.client
-            .get_mut()
-            .write_all(cmd.to_request().as_bytes())
-            .map_err(Pop3Error::Io)?;
+    /// Read the reply to an `AUTH` step: `None` once the server accepted the authentication, or the server challenge otherwise
+    fn read_challenge(&mut self) -> Result<Option<Bytes>> {
+        match self.read_event()? {
+            Event::Response(_)             => Ok(None),
+            Event::Continuation(challenge) => Ok(Some(challenge)),
+        }
+    }
+
+    fn read_response(&mut self) -> Result<Response> {
+        match self.read_event()? {
+            Event::Response(response) => Ok(response),
+            Event::Continuation(_)    => Err(Pop3Error::InvalidResponse),
+        }
+    }
+
+    /// Read from the transport until the protocol has a complete reply
+    fn read_event(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.protocol.poll() {
+                return event;
+            }
+
+            self.fill()?;
+        }
+    }
+
+    /// Read from the transport until the protocol has the next piece of a streamed reply
+    fn read_chunk(&mut self) -> Result<Chunk> {
+        loop {
+            if let Some(chunk) = self.protocol.poll_chunk() {
+                return Ok(chunk);
+            }
+
+            self.fill()?;
+        }
+    }
+
+    /// Pass the data available from the transport to the protocol
+    fn fill(&mut self) -> Result<()> {
+        let mut buffer = [0u8; 4096];
+
+        let command = self.protocol.awaiting();
+        let result = self.limits.read(command)
+            .and_then(|limit| self.limit(TcpStream::set_read_timeout, limit))
+            .and_then(|()| self.stream.read(&mut buffer).map_err(|e| timed_out(e, command)));
+        let amount = self.abort_on_timeout(result)?;
+
+        if amount == 0 {
+            return Err(Pop3Error::ConnectionClosed)
+        }
+
+        self.protocol.feed(&buffer[..amount]);
+
+        Ok(())
+    }
+
+    fn send(&mut self, cmd: &Command<'_>) -> Result<()> {
+        let request = self.protocol.encode(cmd);
 
-        self.read_response(cmd.is_response_multiline())
+        self.write(cmd.name(), &request)
+    }
+
+    fn write(&mut self, command: &'static str, request: &[u8]) -> Result<()> {
+        if self.protocol.is_closed() {
+            return Err(Pop3Error::ConnectionClosed);
+        }
 
+        let result = self.limits.write(command)
+            .and_then(|limit| self.limit(TcpStream::set_write_timeout, limit))
+            .and_then(|()| self.stream.write_all(request).map_err(|e| timed_out(e, command)));
+
+        self.abort_on_timeout(result)
     }
-}
\ No newline at end of file
+
+    /// End the session if the reply or the request timed out, so their late arrival can not be mistaken for another exchange
+    fn abort_on_timeout<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(Pop3Error::Timeout { .. }) = result {
+            self.protocol.abort();
+        }
+
+        result
+    }
+
+    /// Apply the time limit of the next socket operation, if there is one
+    ///
+    /// A limit is either always or never present within a session, so an absent one never has to be reset.
+    fn limit(&self, set: fn(&TcpStream, Option<Duration>) -> std::io::Result<()>, limit: Option<Duration>) -> Result<()> {
+        match &self.socket {
+            Some(socket) if limit.is_some() => set(socket, limit).map_err(Pop3Error::Io),
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&mut self, cmd: &Command<'_>) -> Result<Response> {
+        self.send(cmd)?;
+
+        self.read_response()
+    }
+}
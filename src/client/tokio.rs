@@ -1,23 +1,122 @@
 use super::*;
 
-use ::tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use ::tokio::net::TcpStream;
+use ::tokio::time::Sleep;
 
-// use std::io::BufRead;
-// use std::io::{BufReader, Write};
+use futures_core::Stream;
 
-use bytes::{Bytes, BytesMut, Buf, BufMut};
+use bytes::Bytes;
 
 
 #[cfg(feature = "with-rustls")]
 use {
-    rustls::StreamOwned,
-    rustls::{ClientConfig, ClientSession},
+    rustls::ClientConfig,
+    rustls::pki_types::ServerName,
     std::sync::Arc,
-    webpki::DNSNameRef,
+    tokio_rustls::{client::TlsStream, TlsConnector},
 };
 
-use crate::{Builder, Result};
+#[cfg(feature = "with-rustls")]
+use crate::{Builder, Security};
+
+use crate::Result;
+
+/// Default transport of [`AsyncClient`]: either a plain TCP socket or a TLS session over it
+///
+/// Any other stream can be used through [`AsyncClient::from_stream`].
+pub enum AsyncStream {
+    /// Plaintext TCP connection
+    Plain(TcpStream),
+    /// TLS session over a TCP connection
+    #[cfg(feature = "with-rustls")]
+    Tls(Box<TlsStream<TcpStream>>),
+    /// The transport has been taken away, e.g. by a failed STLS upgrade
+    #[cfg(feature = "with-rustls")]
+    Closed,
+}
+
+/// Run the I/O operation within the time limit, if any
+async fn timed<T>(limit: Option<Duration>, command: &'static str, io: impl Future<Output = std::io::Result<T>>) -> Result<T> {
+    let result = match limit {
+        Some(limit) => ::tokio::time::timeout(limit, io)
+            .await
+            .map_err(|_| Pop3Error::Timeout { command })?,
+        None => io.await,
+    };
+
+    result.map_err(Pop3Error::Io)
+}
+
+#[cfg(feature = "with-rustls")]
+fn closed<T>() -> Poll<std::io::Result<T>> {
+    Poll::Ready(Err(std::io::ErrorKind::NotConnected.into()))
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "with-rustls")]
+            Self::Tls(s)   => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "with-rustls")]
+            Self::Closed   => closed(),
+        }
+    }
+}
+
+#[cfg(feature = "with-rustls")]
+impl AsyncStream {
+    /// Channel binding data of the TLS connection, `tls-exporter` type ([RFC 9266])
+    ///
+    /// [RFC 9266]: https://tools.ietf.org/html/rfc9266
+    fn channel_binding(&self) -> Option<ChannelBinding> {
+        match self {
+            Self::Tls(s) => s.get_ref().1
+                .export_keying_material(vec![0u8; 32], b"EXPORTER-Channel-Binding", None)
+                .ok()
+                .map(|data| ChannelBinding { kind: "tls-exporter", data }),
+            _ => None,
+        }
+    }
+}
 
 /// The key structure for the crate, delineating capabilities of the POP3 client as per the protocol [RFC]
 ///
@@ -34,12 +133,16 @@ use crate::{Builder, Result};
 /// To find out more, read the output of the error you've got -- it's always a string!
 ///
 /// [RFC]: https://tools.ietf.org/html/rfc1081
-pub struct AsyncClient {
-    #[cfg(feature = "with-rustls")]
-    client: BufReader<StreamOwned<ClientSession, TcpStream>>,
-    #[cfg(not(feature = "with-rustls"))]
-    client: BufReader<TcpStream>,
+pub struct AsyncClient<S = AsyncStream> {
+    stream: S,
+    protocol: Protocol,
+    #[cfg_attr(not(feature = "with-rustls"), allow(dead_code))]
+    host: String,
+    greeting: String,
     authorized: bool,
+    capabilities: Option<Capabilities>,
+    channel_binding: Option<ChannelBinding>,
+    limits: Limits,
 }
 
 impl AsyncClient {
@@ -51,29 +154,197 @@ impl AsyncClient {
     ///
     /// ```no_run
     /// # use std::result::Result;
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     ///let client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     ///
     /// #    Ok(())
     /// # }
     /// ```
     ///
-    /// [`ClientConfig`]: https://docs.rs/rustls/0.15.2/rustls/struct.ClientConfig.html
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
     pub async fn connect(host: &str, port: u16) -> Result<Self> {
-        let mut client = TcpStream::connect((host, port))
+        Self::connect_limited(host, port, Limits::default())
             .await
-            .map(|client| Self {
-                client: BufReader::new(client),
-                authorized: false,
-            })
-            .map_err(Pop3Error::Io)?;
+    }
 
-        client.read_response(false)
+    pub(crate) async fn connect_limited(host: &str, port: u16, limits: Limits) -> Result<Self> {
+        let stream = timed(limits.connect()?, "connect", TcpStream::connect((host, port)))
             .await?;
 
+        Self::from_transport(host, AsyncStream::Plain(stream), limits)
+            .await
+    }
+
+    /// Connect to given host and port over implicit TLS (POP3S, usually port 995).
+    ///
+    /// Server certificates are verified against the [webpki-roots] trust anchors. Use [`Builder::rustls_config`] to supply a custom [`ClientConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    ///let client = AsyncClient::connect_tls("pop.gmail.com", 995).await?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [webpki-roots]: https://docs.rs/webpki-roots
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
+    #[cfg(feature = "with-rustls")]
+    pub async fn connect_tls(host: &str, port: u16) -> Result<Self> {
+        Builder::new(host)
+            .port(port)
+            .security(Security::Tls)
+            .connect_async()
+            .await
+    }
+
+    #[cfg(feature = "with-rustls")]
+    pub(crate) async fn connect_rustls(host: &str, port: u16, config: Arc<ClientConfig>, limits: Limits) -> Result<Self> {
+        let hostname = ServerName::try_from(host.to_string())
+            .map_err(|_| Pop3Error::InvalidDnsName(host.into()))?;
+
+        // The TLS handshake is a part of establishing the connection
+        let connect = async {
+            let stream = TcpStream::connect((host, port)).await?;
+
+            TlsConnector::from(config)
+                .connect(hostname, stream)
+                .await
+        };
+
+        let stream = timed(limits.connect()?, "connect", connect)
+            .await?;
+
+        let mut client = Self::from_transport(host, AsyncStream::Tls(Box::new(stream)), limits)
+            .await?;
+
+        client.channel_binding = client.stream.channel_binding();
+
+        Ok(client)
+    }
+
+    /// Upgrade the plaintext connection to TLS using the `STLS` command ([RFC 2595])
+    ///
+    /// Server certificates are verified against the [webpki-roots] trust anchors for the host the client was connected to. Use a [`Builder`] with [`Security::StlsRequired`] to supply a custom [`ClientConfig`].
+    ///
+    /// Any data the server sent after the `+OK` response and before the TLS handshake is discarded, so it can not be injected into the protected session.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// client.stls().await?;
+    /// client.login("sweet_username", "very_secret_password").await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::AlreadyAuthenticated`] if called after a successful authorization, as STLS is only allowed in the AUTHORIZATION state
+    /// - [`Pop3Error::TlsAlreadyActive`] if the connection is already encrypted
+    /// - The server may return an error response if it does not support `STLS`
+    ///
+    /// [RFC 2595]: https://tools.ietf.org/html/rfc2595
+    /// [webpki-roots]: https://docs.rs/webpki-roots
+    /// [`ClientConfig`]: https://docs.rs/rustls/0.23/rustls/client/struct.ClientConfig.html
+    #[cfg(feature = "with-rustls")]
+    pub async fn stls(&mut self) -> Result<()> {
+        let config = Builder::default().tls_config();
+        self.stls_rustls(config).await
+    }
+
+    #[cfg(feature = "with-rustls")]
+    pub(crate) async fn stls_rustls(&mut self, config: Arc<ClientConfig>) -> Result<()> {
+        if self.authorized {
+            return Err(Pop3Error::AlreadyAuthenticated);
+        }
+
+        if matches!(self.stream, AsyncStream::Tls(_)) {
+            return Err(Pop3Error::TlsAlreadyActive);
+        }
+
+        let hostname = ServerName::try_from(self.host.clone())
+            .map_err(|_| Pop3Error::InvalidDnsName(self.host.clone()))?;
+
+        self.request(&Command::Stls).await?;
+        self.capabilities = None;
+
+        // Anything received after the response is discarded
+        self.protocol.discard();
+
+        let stream = match std::mem::replace(&mut self.stream, AsyncStream::Closed) {
+            AsyncStream::Plain(stream) => stream,
+            _ => return Err(Pop3Error::ConnectionClosed),
+        };
+
+        let stream = timed(self.limits.read("STLS")?, "STLS", TlsConnector::from(config).connect(hostname, stream))
+            .await?;
+
+        self.stream = AsyncStream::Tls(Box::new(stream));
+        self.channel_binding = self.stream.channel_binding();
+
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncClient<S> {
+    /// Start a session over an already connected stream, reading the server greeting.
+    ///
+    /// This allows to run over Unix sockets, tunnels, in-memory pipes or a TLS stack the crate does not ship.
+    /// `STLS` and channel binding are only available over the default transport, and no timeouts are applied.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// let (stream, _server) = tokio::io::duplex(4096);
+    /// let client = AsyncClient::from_stream(stream).await?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub async fn from_stream(stream: S) -> Result<Self> {
+        Self::from_transport("", stream, Limits::default())
+            .await
+    }
+
+    async fn from_transport(host: &str, stream: S, limits: Limits) -> Result<Self> {
+        let mut client = Self {
+            stream,
+            protocol: limits.protocol(),
+            host: host.into(),
+            greeting: String::new(),
+            authorized: false,
+            capabilities: None,
+            channel_binding: None,
+            limits,
+        };
+
+        let greeting = client.request(&Command::Greet)
+            .await?;
+
+        client.greeting = String::from_utf8_lossy(greeting.raw())
+            .trim_end()
+            .to_string();
+
         Ok(client)
     }
 
@@ -83,10 +354,10 @@ impl AsyncClient {
     ///
     /// ```no_run
     /// # use std::result::Result;
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// client.login("sweet_username", "very_secret_password").await?;
     /// #    Ok(())
@@ -107,7 +378,7 @@ impl AsyncClient {
             .await
             .map(|_| {
                 self.authorized = true;
-                ()
+                self.capabilities = None;
             })
     }
 
@@ -118,10 +389,10 @@ impl AsyncClient {
     /// ```compile_fail
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # fn main() -> Result<(), String> {
+    /// # fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     ///client.quit()?;
     ///client.noop()?; // Shouldn't compile, as the client has been consumed upon quitting
@@ -143,10 +414,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// let (messages, octets) = client.stat().await?;
     /// assert_eq!(messages, 2);
@@ -155,19 +426,9 @@ impl AsyncClient {
     /// # }
     /// ```
     pub async fn stat(&mut self) -> Result<(u64, u64)> {
-
-        let stat = self.request(&Command::Stat).await
-            .and_then(|r| r.to_string())?;
-
-        let mut s = stat
-            .trim()
-            .split(' ')
-            .map(|i| i.parse::<u64>().map_err(Pop3Error::InvalidNumber));
-
-        Ok((
-            s.next().ok_or(Pop3Error::InvalidResponse)??,
-            s.next().ok_or(Pop3Error::InvalidResponse)??,
-        ))
+        self.request(&Command::Stat)
+            .await
+            .and_then(|r| parse_stat(&r))
     }
 
     /// Show the statistical information on a chosen letter, or all letters. The information in question always required to start with the letter size, but use of additional stats is not regimented in any way.
@@ -178,10 +439,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// let single_stats = client.list(Some(1)).await?; // show info on the letter number 1
     /// let all_stats = client.list(None).await?; // show info on all letters
@@ -197,6 +458,65 @@ impl AsyncClient {
         self.request(&Command::List { id }).await
     }
 
+    /// Show the size of every message in the mailbox, see [`list`](Self::list)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// for entry in client.list_all().await? {
+    ///     println!("message {} is {} octets", entry.id, entry.size);
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// [`Pop3Error::MalformedLine`] if a line of the listing does not follow the [RFC] grammar
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub async fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        self.list(None)
+            .await
+            .and_then(|r| parse_listing(&r, ListEntry::parse))
+    }
+
+    /// Show the size of the chosen message, see [`list`](Self::list)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let entry = client.list_one(1).await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::MalformedLine`] if the response does not follow the [RFC] grammar
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub async fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        self.list(Some(id))
+            .await
+            .and_then(|r| parse_single(&r, ListEntry::parse))
+    }
+
     /// Show the full content of the chosen message
     ///
     ///
@@ -205,10 +525,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// let letter_content = client.retr(5).await?;
     ///
@@ -222,17 +542,119 @@ impl AsyncClient {
     pub async fn retr(&mut self, id: u64) -> Result<Bytes> {
         self.request(&Command::Retr { id })
             .await
-            .map(|s| {
-                let tmp = join_bytes(
-                    &s.raw()[..]
-                        .split(|&b| b == b'\n')
-                        .skip(1)
-                        .collect::<Vec<&[u8]>>(),
-                    b'\n'
-                );
-
-                Bytes::copy_from_slice(&tmp)
-            })
+            .map(message_body)
+    }
+
+    /// Show the full content of the chosen message, unless it exceeds `max_size` octets, see [`retr`](Self::retr)
+    ///
+    /// Overrides the maximum response size of the session, e.g. to accept a message known to be large from [`list_one`](Self::list_one).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let letter_content = client.retr_with_limit(5, 512 << 20).await?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::ResponseTooLarge`] if the message exceeds the limit, in which case the session is closed
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    pub async fn retr_with_limit(&mut self, id: u64, max_size: usize) -> Result<Bytes> {
+        let cmd = Command::Retr { id };
+        let request = self.protocol.encode_limited(&cmd, max_size);
+
+        self.write(cmd.name(), &request).await?;
+        self.read_response()
+            .await
+            .map(message_body)
+    }
+
+    /// Stream the full content of the chosen message as it is received, see [`retr`](Self::retr)
+    ///
+    /// Unlike [`retr`](Self::retr), the message is never held in memory as a whole, so it suits large messages.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let mut message = client.retr_stream(5).await?;
+    ///
+    /// while let Some(chunk) = message.next_chunk().await {
+    ///     println!("received {} octets", chunk?.len());
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// The server may return an error response if:
+    /// - The letter under the given index does not exist in the mailbox
+    /// - The letter under the given index has been marked deleted
+    pub async fn retr_stream(&mut self, id: u64) -> Result<RetrStream<'_, S>> {
+        let cmd = Command::Retr { id };
+        let request = self.protocol.encode_stream(&cmd);
+
+        self.write(cmd.name(), &request).await?;
+        self.read_response().await?;
+
+        Ok(RetrStream { client: self, timer: None, done: false })
+    }
+
+    /// Write the full content of the chosen message to the writer as it is received, see [`retr_stream`](Self::retr_stream)
+    ///
+    /// Returns the number of octets written.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let mut message = Vec::new();
+    /// client.retr_to_writer(5, &mut message).await?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::Io`] if the writer fails, in which case the rest of the message is skipped by the next command
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    pub async fn retr_to_writer<W: AsyncWrite + Unpin>(&mut self, id: u64, writer: &mut W) -> Result<u64> {
+        let mut message = self.retr_stream(id).await?;
+        let mut written = 0;
+
+        while let Some(data) = message.next_chunk().await {
+            let data = data?;
+
+            writer.write_all(&data)
+                .await
+                .map_err(Pop3Error::Io)?;
+
+            written += data.len() as u64;
+        }
+
+        Ok(written)
     }
 
 
@@ -244,10 +666,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// client.dele(3).await?; // now, the THIRD message is marked as deleted, and no new manipulations on it are possible
     ///
@@ -270,10 +692,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// assert!(client.noop().await.is_ok());
     ///
@@ -294,10 +716,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// client.dele(3).await?;
     /// client.dele(4).await?;
@@ -317,10 +739,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// let top = client.top(1, 2).await?; // Get TWO first lines of the FIRST message
     ///
@@ -336,6 +758,37 @@ impl AsyncClient {
         self.request(&Command::Top { id, lines }).await
     }
 
+    /// Parse the header section of the chosen message, without downloading its body (`TOP id 0`)
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let headers = client.headers(1).await?;
+    ///
+    /// if headers.subject().is_some_and(|s| s.contains("invoice")) {
+    ///     let letter_content = client.retr(1).await?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// The server may return an error response if:
+    /// - The letter under the given index does not exist in the mailbox
+    /// - The letter under the given index has been marked deleted
+    /// - The server does not support `TOP`
+    pub async fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.request(&Command::Top { id, lines: 0 })
+            .await
+            .map(|r| Headers::parse(&message_body(r)))
+    }
+
     /// Show the unique ID listing for the chosen message or for all the messages. Unlike message numbering, this ID does not change between sessions.
     ///
     ///
@@ -344,10 +797,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// let uidl_all = client.uidl(None).await?;
     /// let uidl_one = client.uidl(Some(1)).await?;
@@ -364,6 +817,65 @@ impl AsyncClient {
         self.request(&Command::Uidl { id }).await
     }
 
+    /// Show the unique ID of every message in the mailbox, see [`uidl`](Self::uidl)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// for entry in client.uidl_all().await? {
+    ///     println!("message {} has id {}", entry.id, entry.uid);
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// [`Pop3Error::MalformedLine`] if a line of the listing does not follow the [RFC] grammar
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub async fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        self.uidl(None)
+            .await
+            .and_then(|r| parse_listing(&r, UidlEntry::parse))
+    }
+
+    /// Show the unique ID of the chosen message, see [`uidl`](Self::uidl)
+    ///
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let entry = client.uidl_one(1).await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::MalformedLine`] if the response does not follow the [RFC] grammar
+    /// - The server may return an error response if:
+    ///   - The letter under the given index does not exist in the mailbox
+    ///   - The letter under the given index has been marked deleted
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939
+    pub async fn uidl_one(&mut self, id: u64) -> Result<UidlEntry> {
+        self.uidl(Some(id))
+            .await
+            .and_then(|r| parse_single(&r, UidlEntry::parse))
+    }
+
     /// Authorise using the APOP method
     ///
     /// Refer to the POP3 [RFC] for details.
@@ -373,10 +885,10 @@ impl AsyncClient {
     /// ```no_run
     /// # use std::result::Result;
     /// #
-    /// # use pop3_client::AsyncClient;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
     /// #
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), String> {
+    /// # async fn main() -> Result<(), Pop3Error> {
     /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
     /// client.apop("another_sweet_username", "c4c9334bac560ecc979e58001b3e22fb").await?;
     ///
@@ -393,124 +905,396 @@ impl AsyncClient {
         }
         self.request(&Command::Apop { id, token })
             .await
-            .map(|s| {
+            .inspect(|_| {
                 self.authorized = true;
-                s
+                self.capabilities = None;
             })
     }
 
-    #[cfg(feature = "with-rustls")]
-    fn connect_rustls(host: &str, port: u16, config: Arc<ClientConfig>) -> Result<Self> {
-        let hostname = DNSNameRef::try_from_ascii_str(host).map_err(|_| "DNS_NAMEREF_FAILED")?;
-
-        let session = ClientSession::new(&config, hostname);
-        let socket = TcpStream::connect((host, port))
-            .map(BufReader::new)
-            .map_err(|e| format!("{:?}", e))
-            .and_then(|mut client| {
-                let mut buf = String::new();
-                client
-                    .read_line(&mut buf)
-                    .map_err(|e| e.to_string())
-                    .and_then(|_| {
-                        if buf.starts_with("+OK") {
-                            Ok(buf[4..].to_owned())
-                        } else {
-                            Err(buf[5..].to_owned())
-                        }
-                    })
-                    .map(|_| client)
-            })
-            .and_then(|mut client| {
-                client
-                    .get_mut()
-                    .write_all("STLS\r\n".as_bytes())
-                    .map_err(|e| e.to_string())
-                    .and_then(|_| {
-                        let mut buf = String::new();
-                        client
-                            .read_line(&mut buf)
-                            .map_err(|e| e.to_string())
-                            .and_then(|_| {
-                                println!("STLS: {}", &buf);
-                                if buf.starts_with("+OK") {
-                                    Ok(buf[4..].to_owned())
-                                } else {
-                                    Err(buf[5..].to_owned())
-                                }
-                            })
-                    })
-                    .map(|_| client.into_inner())
-            })?;
-
-        let tls_stream = StreamOwned::new(session, socket);
-
-        Ok(Self {
-            client: BufReader::new(tls_stream),
-            authorized: false,
-        })
+    /// Authorise using the APOP method, computing the digest from the server greeting timestamp and the password
+    ///
+    /// Refer to the POP3 [RFC] for details.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// client.apop_with_password("another_sweet_username", "very_secret_password").await?;
+    ///
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::NoApopTimestamp`] if the server greeting has no timestamp, that is the server does not support APOP
+    /// - The server will return error if permission was denied.
+    ///
+    /// [RFC]: https://tools.ietf.org/html/rfc1939#page-15
+    pub async fn apop_with_password(&mut self, id: &str, password: &str) -> Result<Response> {
+        let digest = apop_digest(&self.greeting, password)?;
+
+        self.apop(id, &digest)
+            .await
     }
 
-    async fn read_response(&mut self, multiline: bool) -> Result<Response> {
-        let mut response = BytesMut::new();
-        let mut buffer   = vec![];
+    /// The server greeting, without the `+OK` status
+    pub fn greeting(&self) -> &str {
+        &self.greeting
+    }
 
-        let amount = self.client
-            .read_until(b'\n', &mut buffer)
-            .await
-            .map_err(Pop3Error::Io)?;
+    /// Whether the session has left the AUTHORIZATION state
+    pub fn is_authorized(&self) -> bool {
+        self.authorized
+    }
 
-        if amount == 0 {
-            return Err(Pop3Error::ConnectionClosed)
+    /// Authorise using a SASL mechanism through the `AUTH` command ([RFC 5034])
+    ///
+    /// Built-in mechanisms live in the [`sasl`] module; any type implementing [`SaslMechanism`] can be used as well.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// use pop3_client::sasl;
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// client.authenticate(sasl::Plain::new("sweet_username", "very_secret_password")).await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - [`Pop3Error::AlreadyAuthenticated`] if the session is already authorized
+    /// - Any error of the mechanism, in which case the exchange is cancelled with `*`.
+    ///   If the server accepted nonetheless, or the mechanism fails once it did, e.g. on a wrong SCRAM server signature, the session is closed.
+    /// - The server will return error if permission was denied or the mechanism is not supported.
+    ///
+    /// [RFC 5034]: https://tools.ietf.org/html/rfc5034
+    /// [`sasl`]: crate::sasl
+    pub async fn authenticate<M: SaslMechanism>(&mut self, mut mechanism: M) -> Result<()> {
+        if self.authorized {
+            return Err(Pop3Error::AlreadyAuthenticated);
         }
 
-        if buffer.starts_with(b"+OK") {
-            response.put(&buffer[4..]);
-        } else {
-            let error_msg = std::str::from_utf8(
-                if buffer.len() < 6 { &buffer } else { &buffer[5..] },
-            );
+        mechanism.set_channel_binding(self.channel_binding.clone());
 
-            let err = match error_msg {
-                Ok(v)  => Pop3Error::other(v),
-                Err(e) => Pop3Error::InvalidString(e),
+        let initial = mechanism.initial_response()
+            .map(|r| sasl::encode_initial(&r));
+
+        // Too long initial response is sent in reply to the first, empty, challenge instead
+        let (inline, mut pending) = match initial {
+            Some(r) if sasl::fits_auth_line(mechanism.name(), &r) => (Some(r), None),
+            r => (None, r),
+        };
+
+        self.send(&Command::Auth { mechanism: mechanism.name(), initial: inline.as_deref() }).await?;
+
+        loop {
+            let challenge = match self.read_challenge().await {
+                Ok(Some(challenge)) => challenge,
+                Ok(None) => break,
+                Err(e @ Pop3Error::Server { .. }) => return Err(mechanism.failure(e)),
+                Err(e) => return Err(e),
             };
 
-            return Err(err)
+            let response = match pending.take() {
+                Some(r) => Ok(r),
+                None    => sasl::decode(&challenge)
+                    .and_then(|c| mechanism.respond(&c))
+                    .map(|r| sasl::encode(&r)),
+            };
+
+            match response {
+                Ok(data) => self.send(&Command::AuthResponse { data: &data }).await?,
+                Err(e) => {
+                    self.send(&Command::AuthResponse { data: "*" }).await?;
+                    // The server must reject the cancelled exchange, the session can not be trusted otherwise
+                    if !matches!(self.read_event().await, Err(Pop3Error::Server { .. })) {
+                        self.protocol.abort();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        // The server accepted, but failed to authenticate itself, e.g. with a wrong SCRAM signature
+        if let Err(e) = mechanism.complete() {
+            self.protocol.abort();
+            return Err(e);
         }
 
-        if multiline {
-            loop {
-                buffer.clear();
+        self.authorized = true;
+        self.capabilities = None;
 
-                let amount = self.client
-                    .read_until(b'\n', &mut buffer)
-                    .await
-                    .map_err(Pop3Error::Io)?;
+        Ok(())
+    }
 
-                if amount == 0 {
-                    return Err(Pop3Error::ConnectionClosed)
-                }
+    /// List the server capabilities (that's what the `CAPA` command does, see [RFC 2449]).
+    ///
+    /// The result is cached, so subsequent calls do not hit the server until the capabilities may change, that is after `STLS` or a successful authorization.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let capabilities = client.capa().await?;
+    ///
+    /// if capabilities.supports_uidl() {
+    ///     let uidl_all = client.uidl(None).await?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// The server may return an error response if it does not support `CAPA`.
+    ///
+    /// [RFC 2449]: https://tools.ietf.org/html/rfc2449
+    pub async fn capa(&mut self) -> Result<Capabilities> {
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
+        }
+
+        let capabilities = self.request(&Command::Capa).await
+            .and_then(|r| Capabilities::parse(&r))?;
+
+        self.capabilities = Some(capabilities.clone());
+
+        Ok(capabilities)
+    }
+
+    /// Send a batch of commands and read their replies in order, e.g. to retrieve many messages over a slow link
+    ///
+    /// When the server announces `PIPELINING` ([RFC 2449] section 6.6), the commands are written in groups without waiting for the replies,
+    /// keeping a bounded number of them in flight. Otherwise they are sent one at a time.
+    /// A server rejecting `CAPA` is then taken to announce no capabilities, until they may change.
+    ///
+    /// Commands changing the session state (`USER`, `PASS`, `APOP`, `AUTH`, `STLS`, `QUIT`) are not sent and fail with [`Pop3Error::NotPipelinable`].
+    /// An error response only fails the corresponding command, while the replies after a transport failure fail with [`Pop3Error::ConnectionClosed`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// #
+    /// # use pop3_client::{Command, AsyncClient, Pop3Error};
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// let commands: Vec<_> = (1..=100).map(|id| Command::Retr { id }).collect();
+    ///
+    /// for reply in client.pipeline(&commands).await {
+    ///     let message = reply?;
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [RFC 2449]: https://tools.ietf.org/html/rfc2449#section-6.6
+    pub async fn pipeline(&mut self, commands: &[Command<'_>]) -> Vec<Result<Response>> {
+        let pipelining = match self.capa().await {
+            Ok(capabilities) => capabilities.supports_pipelining(),
+            Err(Pop3Error::Server { .. }) => {
+                // A server without CAPA announces nothing, and is not asked again before the next batch
+                self.capabilities = Some(Capabilities::default());
+                false
+            }
+            Err(_) => false,
+        };
+        let mut pipeline = Pipeline::new(commands, pipelining);
 
-                if buffer == b".\r\n" {
+        loop {
+            if let Some((command, request)) = pipeline.refill(&mut self.protocol) {
+                if let Err(e) = self.write(command, &request).await {
+                    pipeline.complete(Err(e));
+                    self.protocol.abort();
                     break;
                 }
+            }
+
+            if !pipeline.is_pending() {
+                break;
+            }
+
+            // The commands left in flight would take the replies to the next ones
+            if !pipeline.complete(self.read_response().await) {
+                self.protocol.abort();
+                break;
+            }
+        }
+
+        pipeline.finish()
+    }
+
+    /// Read the reply to an `AUTH` step: `None` once the server accepted the authentication, or the server challenge otherwise
+    async fn read_challenge(&mut self) -> Result<Option<Bytes>> {
+        match self.read_event().await? {
+            Event::Response(_)             => Ok(None),
+            Event::Continuation(challenge) => Ok(Some(challenge)),
+        }
+    }
+
+    async fn read_response(&mut self) -> Result<Response> {
+        match self.read_event().await? {
+            Event::Response(response) => Ok(response),
+            Event::Continuation(_)    => Err(Pop3Error::InvalidResponse),
+        }
+    }
+
+    /// Read from the transport until the protocol has a complete reply
+    async fn read_event(&mut self) -> Result<Event> {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            if let Some(event) = self.protocol.poll() {
+                return event;
+            }
+
+            let command = self.protocol.awaiting();
+
+            let result = match self.limits.read(command) {
+                Ok(limit) => timed(limit, command, self.stream.read(&mut buffer)).await,
+                Err(e) => Err(e),
+            };
+            let amount = self.abort_on_timeout(result)?;
 
-                response.put(&buffer[..]);
+            if amount == 0 {
+                return Err(Pop3Error::ConnectionClosed)
             }
+
+            self.protocol.feed(&buffer[..amount]);
+        }
+    }
+
+    async fn send(&mut self, cmd: &Command<'_>) -> Result<()> {
+        let request = self.protocol.encode(cmd);
+
+        self.write(cmd.name(), &request)
+            .await
+    }
+
+    async fn write(&mut self, command: &'static str, request: &[u8]) -> Result<()> {
+        if self.protocol.is_closed() {
+            return Err(Pop3Error::ConnectionClosed);
         }
 
-        Ok(Response::new(response.freeze()))
+        let result = match self.limits.write(command) {
+            Ok(limit) => timed(limit, command, self.stream.write_all(request)).await,
+            Err(e) => Err(e),
+        };
+
+        self.abort_on_timeout(result)
+    }
+
+    /// End the session if the reply or the request timed out, so their late arrival can not be mistaken for another exchange
+    fn abort_on_timeout<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(Pop3Error::Timeout { .. }) = result {
+            self.protocol.abort();
+        }
+
+        result
     }
 
     async fn request(&mut self, cmd: &Command<'_>) -> Result<Response> {
-        self.client
-            .get_mut()
-            .write_all(cmd.to_request().as_bytes())
+        self.send(cmd).await?;
+
+        self.read_response()
             .await
-            .map_err(Pop3Error::Io)?;
+    }
+}
 
-        self.read_response(cmd.is_response_multiline())
+/// Content of a message being retrieved, see [`AsyncClient::retr_stream`]
+///
+/// Yields the unstuffed message data in chunks as it is received, the chunks do not necessarily end at line boundaries.
+/// If the stream is dropped before the end of the message, the rest of it is skipped by the next command.
+pub struct RetrStream<'a, S = AsyncStream> {
+    client: &'a mut AsyncClient<S>,
+    /// Read timeout of the pending read
+    timer: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RetrStream<'_, S> {
+    /// The next piece of the message, `None` once it has been received completely
+    pub async fn next_chunk(&mut self) -> Option<Result<Bytes>> {
+        std::future::poll_fn(|cx| self.poll_chunk(cx))
             .await
     }
+
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match self.client.protocol.poll_chunk() {
+                Some(Chunk::Data(data)) => return Poll::Ready(Some(Ok(data))),
+                Some(Chunk::End) => {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+                None => {}
+            }
+
+            if let Err(e) = ready!(self.poll_fill(cx)) {
+                self.done = true;
+                return Poll::Ready(Some(self.client.abort_on_timeout(Err(e))));
+            }
+        }
+    }
+
+    /// Pass the data available from the transport to the protocol
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let command = self.client.protocol.awaiting();
+
+        if self.timer.is_none() {
+            if let Some(limit) = self.client.limits.read(command)? {
+                self.timer = Some(Box::pin(::tokio::time::sleep(limit)));
+            }
+        }
+
+        if let Some(timer) = &mut self.timer {
+            if timer.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(Pop3Error::Timeout { command }));
+            }
+        }
+
+        let mut buffer = [0u8; 4096];
+        let mut buffer = ReadBuf::new(&mut buffer);
+
+        ready!(Pin::new(&mut self.client.stream).poll_read(cx, &mut buffer))
+            .map_err(Pop3Error::Io)?;
+
+        self.timer = None;
+
+        if buffer.filled().is_empty() {
+            return Poll::Ready(Err(Pop3Error::ConnectionClosed));
+        }
+
+        self.client.protocol.feed(buffer.filled());
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Stream for RetrStream<'_, S> {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_chunk(cx)
+    }
 }
@@ -1,5 +1,62 @@
+use std::fmt;
+
 use thiserror::Error;
 
+/// Extended response code of a negative server reply ([RFC 2449] section 8, [RFC 3206])
+///
+/// Sent in square brackets at the start of the reply text by servers announcing the `RESP-CODES` or `AUTH-RESP-CODE` capability.
+///
+/// [RFC 2449]: https://tools.ietf.org/html/rfc2449#section-8
+/// [RFC 3206]: https://tools.ietf.org/html/rfc3206
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ResponseCode {
+    /// The maildrop is locked by another session, which may go away shortly
+    InUse,
+    /// The user logs in too often, the next attempt should wait for the announced `LOGIN-DELAY`
+    LoginDelay,
+    /// A temporary system failure, the same request is expected to succeed later
+    SysTemp,
+    /// A permanent system failure, which requires action from the administrator
+    SysPerm,
+    /// The credentials were rejected, retrying with the same ones is pointless
+    Auth,
+    /// Any other code, as sent by the server
+    Other(String),
+}
+
+impl ResponseCode {
+    /// Parse a response code, without the square brackets
+    pub fn parse(code: &str) -> Self {
+        match code.to_ascii_uppercase().as_str() {
+            "IN-USE"      => Self::InUse,
+            "LOGIN-DELAY" => Self::LoginDelay,
+            "SYS/TEMP"    => Self::SysTemp,
+            "SYS/PERM"    => Self::SysPerm,
+            "AUTH"        => Self::Auth,
+            _             => Self::Other(code.to_string()),
+        }
+    }
+
+    /// Whether the same request may succeed later without any change
+    pub fn is_temporary(&self) -> bool {
+        matches!(self, Self::InUse | Self::LoginDelay | Self::SysTemp)
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InUse      => "IN-USE",
+            Self::LoginDelay => "LOGIN-DELAY",
+            Self::SysTemp    => "SYS/TEMP",
+            Self::SysPerm    => "SYS/PERM",
+            Self::Auth       => "AUTH",
+            Self::Other(s)   => s,
+        })
+    }
+}
+
+
 #[derive(Error, Debug)]
 pub enum Pop3Error {
     #[error("Stream connection closed")]
@@ -8,9 +65,40 @@ pub enum Pop3Error {
     #[error("Already authenticated")]
     AlreadyAuthenticated,
 
+    #[error("TLS is already active")]
+    TlsAlreadyActive,
+
+    #[error("Server greeting has no APOP timestamp")]
+    NoApopTimestamp,
+
+    #[error("No supported authentication mechanism")]
+    NoAuthMechanism,
+
+    #[error("Timed out waiting for {command}")]
+    Timeout { command: &'static str },
+
+    #[error("{command} cannot be pipelined")]
+    NotPipelinable { command: &'static str },
+
+    #[error("Response to {command} exceeds {limit} octets")]
+    ResponseTooLarge { command: &'static str, limit: usize },
+
+    #[error("Message {id} is not in the maildrop")]
+    UnknownMessage { id: u64 },
+
+    #[error("Connection lost before the deletion of messages {ids:?} was committed")]
+    DeletionsLost { ids: Vec<u64> },
+
     #[error("IO: {0}")]
     Io(#[from] std::io::Error),
 
+    #[cfg(feature = "with-rustls")]
+    #[error("TLS: {0}")]
+    Tls(#[from] rustls::Error),
+
+    #[error("Invalid DNS name: {0}")]
+    InvalidDnsName(String),
+
 
     #[error("Number parsing error: {0}")]
     InvalidNumber(std::num::ParseIntError),
@@ -21,9 +109,30 @@ pub enum Pop3Error {
     #[error("Invalid response")]
     InvalidResponse,
 
+    #[error("Malformed response line: {0:?}")]
+    MalformedLine(String),
+
+    #[error("SASL: {0}")]
+    Sasl(String),
+
+    #[error("OAuth authentication failed with status {status}")]
+    OAuth {
+        status: String,
+        scope: Option<String>,
+        schemes: Option<String>,
+        openid_configuration: Option<String>,
+    },
+
+    #[deprecated(note = "negative replies are reported as `Pop3Error::Server`")]
     #[error("Other error: {0}")]
     OtherString(String),
 
+    #[error("Server error: {}{text}", code.as_ref().map(|c| format!("[{c}] ")).unwrap_or_default())]
+    Server {
+        code: Option<ResponseCode>,
+        text: String,
+    },
+
     // #[error("invalid header (expected {expected:?}, found {found:?})")]
     // InvalidHeader {
     //     expected: String,
@@ -34,7 +143,68 @@ pub enum Pop3Error {
 }
 
 impl Pop3Error {
+    #[deprecated(note = "negative replies are reported as `Pop3Error::Server`")]
+    #[allow(deprecated)]
     pub fn other<E: AsRef<str>>(err: E) -> Self {
         Self::OtherString(err.as_ref().to_string())
     }
+
+    /// Parse the text of a negative status line, following `-ERR`
+    pub(crate) fn server(text: &str) -> Self {
+        let text = text.trim();
+
+        match text.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+            Some((code, rest)) => Self::Server {
+                code: Some(ResponseCode::parse(code)),
+                text: rest.trim_start().to_string(),
+            },
+            None => Self::Server { code: None, text: text.to_string() },
+        }
+    }
+
+    /// Whether the connection to the server failed, so that a new session may succeed where this one did not
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(self, Self::Io(_) | Self::ConnectionClosed | Self::Timeout { .. })
+    }
+
+    /// Extended response code of a negative server reply, if any
+    pub fn code(&self) -> Option<&ResponseCode> {
+        match self {
+            Self::Server { code, .. } => code.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server() {
+        let error = Pop3Error::server(" [IN-USE] Do you have another POP session running?\r\n");
+        assert_eq!(error.code(), Some(&ResponseCode::InUse));
+        assert_eq!(error.to_string(), "Server error: [IN-USE] Do you have another POP session running?");
+
+        let error = Pop3Error::server(" [sys/temp] try again\r\n");
+        assert!(error.code().is_some_and(ResponseCode::is_temporary));
+
+        let error = Pop3Error::server(" [AUTH/EXPIRED] password expired\r\n");
+        assert_eq!(error.code(), Some(&ResponseCode::Other("AUTH/EXPIRED".to_string())));
+
+        let error = Pop3Error::server(" no such message\r\n");
+        assert!(matches!(&error, Pop3Error::Server { code: None, text } if text == "no such message"));
+        assert_eq!(error.to_string(), "Server error: no such message");
+
+        // An unterminated bracket is not a code
+        assert_eq!(Pop3Error::server(" [IN-USE locked").code(), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn other() {
+        let error = Pop3Error::other("custom");
+        assert!(matches!(&error, Pop3Error::OtherString(text) if text == "custom"));
+        assert_eq!(error.to_string(), "Other error: custom");
+    }
 }
\ No newline at end of file
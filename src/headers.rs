@@ -0,0 +1,522 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64;
+
+/// Header section of a message ([RFC 5322] section 2.2), as returned by `TOP id 0`
+///
+/// Fields keep their order and are unfolded, that is continuation lines are joined to the line they continue.
+/// Values are raw, except for the typed accessors which decode [RFC 2047] encoded words.
+///
+/// [RFC 5322]: https://tools.ietf.org/html/rfc5322#section-2.2
+/// [RFC 2047]: https://tools.ietf.org/html/rfc2047
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+/// A mailbox of an address list, such as `"Jane Doe" <jane@example.com>`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Address {
+    /// Display name, decoded
+    pub name:  Option<String>,
+    /// The `local@domain` address
+    pub email: String,
+}
+
+/// A date and time with the offset of its zone ([RFC 5322] section 3.3)
+///
+/// [RFC 5322]: https://tools.ietf.org/html/rfc5322#section-3.3
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Date {
+    pub year:   i32,
+    pub month:  u8,
+    pub day:    u8,
+    pub hour:   u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Offset of the zone from UTC in minutes, east being positive
+    pub offset: i32,
+}
+
+impl Headers {
+    /// Parse the header section at the start of `data`, up to the first empty line
+    ///
+    /// Lines that are neither a field nor a continuation are skipped, and invalid UTF-8 is replaced.
+    pub fn parse(data: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(data);
+        let mut fields: Vec<(String, String)> = Vec::new();
+
+        for line in text.split('\n') {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+
+            if line.is_empty() {
+                break;
+            }
+
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push_str(line);
+                }
+                continue;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                fields.push((name.trim_end().to_string(), value.trim_start().to_string()));
+            }
+        }
+
+        Self { fields }
+    }
+
+    /// All fields, in the order of the message
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Raw value of the first field with the given name, compared case-insensitively
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Raw values of all the fields with the given name, compared case-insensitively
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `Message-ID` field, without the angle brackets
+    pub fn message_id(&self) -> Option<&str> {
+        self.get("Message-ID")
+            .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>'))
+    }
+
+    /// The `Date` field, or `None` if it is missing or malformed
+    pub fn date(&self) -> Option<Date> {
+        self.get("Date").and_then(Date::parse)
+    }
+
+    /// The decoded `Subject` field
+    pub fn subject(&self) -> Option<String> {
+        self.get("Subject").map(decode_words)
+    }
+
+    /// The mailboxes of the `From` field
+    pub fn from(&self) -> Vec<Address> {
+        self.addresses("From")
+    }
+
+    /// The mailboxes of all the `To` fields
+    pub fn to(&self) -> Vec<Address> {
+        self.addresses("To")
+    }
+
+    fn addresses(&self, name: &str) -> Vec<Address> {
+        self.get_all(name)
+            .flat_map(Address::parse_list)
+            .collect()
+    }
+}
+
+impl Address {
+    /// Parse an address list, flattening groups; malformed entries are skipped
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        let mut addresses = Vec::new();
+        let mut current = String::new();
+        let mut quoted = false;
+        let mut escaped = false;
+        let mut angle = false;
+        let mut comment = 0usize;
+
+        for c in value.chars() {
+            if escaped {
+                escaped = false;
+                current.push(c);
+                continue;
+            }
+
+            match c {
+                '\\' if quoted || comment > 0 => escaped = true,
+                '"' if comment == 0 => quoted = !quoted,
+                '(' if !quoted => comment += 1,
+                ')' if !quoted && comment > 0 => comment -= 1,
+                '<' if !quoted && comment == 0 => angle = true,
+                '>' if !quoted && comment == 0 => angle = false,
+                // A group name ends at the colon, and its list at the semicolon
+                ':' if !quoted && comment == 0 && !angle => {
+                    current.clear();
+                    continue;
+                }
+                ',' | ';' if !quoted && comment == 0 && !angle => {
+                    addresses.extend(Self::parse(&current));
+                    current.clear();
+                    continue;
+                }
+                _ => (),
+            }
+
+            current.push(c);
+        }
+
+        addresses.extend(Self::parse(&current));
+        addresses
+    }
+
+    /// Parse a single mailbox, either `name <local@domain>` or `local@domain (name)`
+    fn parse(mailbox: &str) -> Option<Self> {
+        let mailbox = mailbox.trim();
+
+        if let (Some(start), Some(end)) = (mailbox.find('<'), mailbox.rfind('>')) {
+            let email = mailbox.get(start + 1..end)?.trim();
+            let name = unquote(&mailbox[..start]);
+
+            return (!email.is_empty()).then(|| Self {
+                name:  (!name.is_empty()).then(|| decode_words(&name)),
+                email: email.to_string(),
+            });
+        }
+
+        let (email, comment) = match mailbox.split_once('(') {
+            Some((email, comment)) => (email.trim(), Some(comment.trim_end().trim_end_matches(')').trim())),
+            None                   => (mailbox, None),
+        };
+
+        email.contains('@').then(|| Self {
+            name:  comment.filter(|c| !c.is_empty()).map(decode_words),
+            email: email.to_string(),
+        })
+    }
+}
+
+/// Strip the quotes and escapes of a display name
+fn unquote(phrase: &str) -> String {
+    let mut name = String::new();
+    let mut escaped = false;
+
+    for c in phrase.trim().chars() {
+        match c {
+            _ if escaped => {
+                name.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            '"'  => (),
+            _    => name.push(c),
+        }
+    }
+
+    name.trim().to_string()
+}
+
+impl Date {
+    /// Parse a `date-time` ([RFC 5322] section 3.3), including the obsolete forms of section 4.3
+    ///
+    /// [RFC 5322]: https://tools.ietf.org/html/rfc5322#section-3.3
+    pub fn parse(value: &str) -> Option<Self> {
+        let text = strip_comments(value);
+        let mut tokens = text
+            .split([' ', '\t', ','])
+            .filter(|t| !t.is_empty())
+            .peekable();
+
+        // The day of the week is redundant
+        if tokens.peek()?.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            tokens.next();
+        }
+
+        let day: u8 = tokens.next()?.parse().ok()?;
+        let month = month(tokens.next()?)?;
+
+        let year = tokens.next()?;
+        let year = match (year.len(), year.parse::<i32>().ok()?) {
+            (2, y) if y < 50 => 2000 + y,
+            (2 | 3, y)       => 1900 + y,
+            (_, y)           => y,
+        };
+
+        let mut time = tokens.next()?.split(':');
+        let hour: u8 = time.next()?.parse().ok()?;
+        let minute: u8 = time.next()?.parse().ok()?;
+        let second: u8 = match time.next() {
+            Some(s) => s.parse().ok()?,
+            None    => 0,
+        };
+
+        let offset = zone(tokens.next().unwrap_or("-0000"))?;
+
+        let valid = (1..=31).contains(&day) && hour < 24 && minute < 60 && second <= 60;
+
+        valid.then_some(Self { year, month, day, hour, minute, second, offset })
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn unix_timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let seconds = i64::from(self.hour) * 3600 + i64::from(self.minute) * 60 + i64::from(self.second);
+
+        days * 86400 + seconds - i64::from(self.offset) * 60
+    }
+
+    /// The instant this date designates
+    pub fn to_system_time(&self) -> SystemTime {
+        let timestamp = self.unix_timestamp();
+
+        match u64::try_from(timestamp) {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_)   => UNIX_EPOCH - Duration::from_secs(timestamp.unsigned_abs()),
+        }
+    }
+}
+
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0usize;
+
+    value
+        .chars()
+        .filter(|&c| match c {
+            '(' => { depth += 1; false }
+            ')' => { depth = depth.saturating_sub(1); false }
+            _   => depth == 0,
+        })
+        .collect()
+}
+
+fn month(name: &str) -> Option<u8> {
+    const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+    let name = name.get(..3)?.to_ascii_lowercase();
+
+    MONTHS.iter()
+        .position(|m| *m == name)
+        .map(|i| i as u8 + 1)
+}
+
+/// Offset in minutes of a numeric zone or of an obsolete zone name
+fn zone(zone: &str) -> Option<i32> {
+    if let Some(digits) = zone.strip_prefix(['+', '-']) {
+        if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let hhmm: i32 = digits.parse().ok()?;
+        let minutes = hhmm / 100 * 60 + hhmm % 100;
+
+        return Some(if zone.starts_with('-') { -minutes } else { minutes });
+    }
+
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "UT" | "GMT" | "Z" => 0,
+        "EDT"              => -4,
+        "EST" | "CDT"      => -5,
+        "CST" | "MDT"      => -6,
+        "MST" | "PDT"      => -7,
+        "PST"              => -8,
+        // Military zones are treated as unknown, as the RFC requires
+        z if z.len() == 1 && z.bytes().all(|b| b.is_ascii_alphabetic()) => 0,
+        _ => return None,
+    };
+
+    Some(hours * 60)
+}
+
+/// Days between the Unix epoch and a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Decode the [RFC 2047] encoded words of a field value, such as `=?UTF-8?B?w6k=?=`
+///
+/// Whitespace between adjacent encoded words is dropped. Words that are malformed or in an unknown charset are kept as they are.
+///
+/// [RFC 2047]: https://tools.ietf.org/html/rfc2047
+pub(crate) fn decode_words(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let before = &rest[..start];
+
+        match decode_word(&rest[start..]) {
+            Some((word, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&word);
+                rest = &rest[start + len..];
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decode the encoded word at the start of `word`, returning the text and the length of the word
+fn decode_word(word: &str) -> Option<(String, usize)> {
+    let mut parts = word.get(2..)?.splitn(3, '?');
+
+    let charset  = parts.next()?;
+    let encoding = parts.next()?;
+    let (text, _) = parts.next()?.split_once("?=")?;
+
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => BASE64.decode(text.trim_end_matches('=')).ok()?,
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + text.len() + 2;
+
+    // Language tags of RFC 2231 are ignored
+    let charset = charset.split('*').next()?;
+
+    Some((to_utf8(charset, &bytes)?, len))
+}
+
+/// Undo the `Q` encoding: `_` stands for a space, and `=XX` for an octet
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+
+    while let Some(b) = iter.next() {
+        match b {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b => bytes.push(b),
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Convert text in the given charset to UTF-8, for the charsets commonly found in headers, or any known one with the `mime` feature
+pub(crate) fn to_utf8(charset: &str, bytes: &[u8]) -> Option<String> {
+    #[cfg(feature = "mime")]
+    if let Some(encoding) = encoding_rs::Encoding::for_label(charset.trim().as_bytes()) {
+        return Some(encoding.decode_without_bom_handling(bytes).0.into_owned());
+    }
+
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(String::from_utf8_lossy(bytes).into_owned()),
+        "iso-8859-1" | "latin1" | "l1"          => Some(bytes.iter().map(|&b| char::from(b)).collect()),
+        "windows-1252" | "cp1252"               => Some(bytes.iter().map(|&b| windows_1252(b)).collect()),
+        _ => None,
+    }
+}
+
+/// Windows-1252 is ISO-8859-1 with printable characters in place of most C1 controls
+fn windows_1252(b: u8) -> char {
+    const C1: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+        '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+
+    match b {
+        0x80..=0x9f => C1[usize::from(b - 0x80)],
+        _ => char::from(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADERS: &[u8] = b"Return-Path: <jane@example.com>\r\n\
+        Message-ID: <1234@local.machine.example>\r\n\
+        Date: Fri, 21 Nov 1997 09:55:06 -0600\r\n\
+        From: =?ISO-8859-1?Q?Andr=E9?= Pirard <PIRARD@vm1.ulg.ac.be>\r\n\
+        To: \"Doe, John\" <john@example.com>,\r\n \
+        mary@example.net (Mary Smith)\r\n\
+        To: Team: a@example.org, b@example.org;\r\n\
+        Subject: =?UTF-8?B?w6l0w6k=?=\r\n\
+        \t=?UTF-8?Q?_report?= for (2024)\r\n\
+        \r\n\
+        Not: a header\r\n";
+
+    #[test]
+    fn parse() {
+        let headers = Headers::parse(HEADERS);
+
+        assert_eq!(headers.iter().count(), 7);
+        assert_eq!(headers.get("return-path"), Some("<jane@example.com>"));
+        assert_eq!(headers.get("Not"), None);
+        assert_eq!(headers.get_all("TO").count(), 2);
+        assert_eq!(headers.message_id(), Some("1234@local.machine.example"));
+    }
+
+    #[test]
+    fn typed() {
+        let headers = Headers::parse(HEADERS);
+
+        assert_eq!(headers.subject().as_deref(), Some("été report for (2024)"));
+
+        assert_eq!(headers.from(), vec![Address {
+            name:  Some("André Pirard".into()),
+            email: "PIRARD@vm1.ulg.ac.be".into(),
+        }]);
+
+        let to: Vec<_> = headers.to().into_iter().map(|a| (a.name, a.email)).collect();
+        assert_eq!(to, vec![
+            (Some("Doe, John".into()), "john@example.com".into()),
+            (Some("Mary Smith".into()), "mary@example.net".into()),
+            (None, "a@example.org".into()),
+            (None, "b@example.org".into()),
+        ]);
+
+        let date = headers.date().unwrap();
+        assert_eq!((date.year, date.month, date.day, date.hour, date.offset), (1997, 11, 21, 9, -360));
+        assert_eq!(date.unix_timestamp(), 880127706);
+    }
+
+    #[test]
+    fn dates() {
+        let date = Date::parse("1 Jan 70 00:00 GMT (comment)").unwrap();
+        assert_eq!(date.unix_timestamp(), 0);
+        assert_eq!(date.to_system_time(), UNIX_EPOCH);
+
+        let date = Date::parse("Thu, 13 Feb 1969 23:32 -0330").unwrap();
+        assert_eq!(date.unix_timestamp(), -27723480);
+        assert_eq!(Date::parse("21 Nov 97 09:55:06 EST").unwrap().year, 1997);
+
+        assert_eq!(Date::parse("yesterday"), None);
+        assert_eq!(Date::parse("32 Nov 1997 09:55:06 +0000"), None);
+        assert_eq!(Date::parse("21 Nov 1997 09:55:06 +06"), None);
+    }
+
+    #[test]
+    fn encoded_words() {
+        assert_eq!(decode_words("=?utf-8?q?a?= =?utf-8?q?b?="), "ab");
+        assert_eq!(decode_words("a =?utf-8?q?b?= c"), "a b c");
+        assert_eq!(decode_words("=?windows-1252?Q?=93hi=94?="), "“hi”");
+        assert_eq!(decode_words("=?UTF-8*en?B?aGk?="), "hi");
+        // Unknown charsets and malformed words are kept
+        assert_eq!(decode_words("=?x-unknown?B?aGk=?="), "=?x-unknown?B?aGk=?=");
+        assert_eq!(decode_words("=?utf-8?x?y?= =?"), "=?utf-8?x?y?= =?");
+    }
+}
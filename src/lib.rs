@@ -1,20 +1,34 @@
 mod builder;
+mod capabilities;
 mod client;
 mod error;
+mod headers;
+mod listing;
+#[cfg(feature = "mime")]
+mod mime;
+mod protocol;
 mod request;
 mod response;
 
-#[cfg(feature = "with-rustls")]
-use {
-    rustls::StreamOwned,
-    rustls::{ClientConfig, ClientSession},
-    std::sync::Arc,
-    webpki::DNSNameRef,
-};
+pub mod maildir;
+pub mod mbox;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+pub mod reconnect;
+pub mod sasl;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+pub mod session;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-sync"))]
+pub mod sync;
 
-pub use error::Pop3Error;
-pub use builder::Builder;
+pub use error::{Pop3Error, ResponseCode};
+pub use builder::{Builder, Mechanism, Security};
+pub use capabilities::{Capabilities, Expire};
+pub use headers::{Address, Date, Headers};
 pub use client::*;
+pub use listing::{ListEntry, UidlEntry};
+#[cfg(feature = "mime")]
+pub use mime::{ContentType, Message, Part};
 pub use request::Command;
 pub use response::Response;
+pub use sasl::SaslMechanism;
 
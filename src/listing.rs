@@ -0,0 +1,125 @@
+use crate::{Pop3Error, Response};
+
+/// Longest unique-id allowed by [RFC 1939]
+///
+/// [RFC 1939]: https://tools.ietf.org/html/rfc1939#page-12
+const MAX_UID_LEN: usize = 70;
+
+/// A scan listing, as returned by the `LIST` command
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ListEntry {
+    /// Message number in the current session
+    pub id:   u64,
+    /// Exact size of the message in octets
+    pub size: u64,
+}
+
+/// A unique-id listing, as returned by the `UIDL` command
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct UidlEntry {
+    /// Message number in the current session
+    pub id:  u64,
+    /// Identifier of the message, persistent across sessions
+    pub uid: String,
+}
+
+impl ListEntry {
+    /// Parse a `msg-number SP size` line; anything following the size is ignored, as permitted by the RFC
+    pub fn parse(line: &str) -> Result<Self, Pop3Error> {
+        let mut parts = line.trim_end_matches(['\r', '\n']).splitn(3, ' ');
+
+        let id   = parts.next().and_then(parse_number);
+        let size = parts.next().and_then(parse_number);
+
+        match (id, size) {
+            (Some(id), Some(size)) => Ok(Self { id, size }),
+            _ => Err(Pop3Error::MalformedLine(line.into())),
+        }
+    }
+}
+
+impl UidlEntry {
+    /// Parse a `msg-number SP unique-id` line, the unique-id being 1 to 70 characters in the range 0x21 to 0x7E
+    pub fn parse(line: &str) -> Result<Self, Pop3Error> {
+        let mut parts = line.trim_end_matches(['\r', '\n']).splitn(2, ' ');
+
+        let id  = parts.next().and_then(parse_number);
+        let uid = parts.next().filter(|uid| {
+            (1..=MAX_UID_LEN).contains(&uid.len())
+                && uid.bytes().all(|b| (0x21..=0x7E).contains(&b))
+        });
+
+        match (id, uid) {
+            (Some(id), Some(uid)) => Ok(Self { id, uid: uid.into() }),
+            _ => Err(Pop3Error::MalformedLine(line.into())),
+        }
+    }
+}
+
+/// Parse a message number or a size: decimal digits only, no sign
+fn parse_number(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+/// Parse the lines of a multiline listing, skipping the text of the status line
+pub(crate) fn parse_listing<T>(response: &Response, parse: fn(&str) -> Result<T, Pop3Error>) -> Result<Vec<T>, Pop3Error> {
+    response
+        .to_string()?
+        .lines()
+        .skip(1)
+        .map(parse)
+        .collect()
+}
+
+/// Parse a single line listing, carried by the status line
+pub(crate) fn parse_single<T>(response: &Response, parse: fn(&str) -> Result<T, Pop3Error>) -> Result<T, Pop3Error> {
+    parse(&response.to_string()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytes::Bytes;
+
+    #[test]
+    fn list_entry() {
+        assert_eq!(ListEntry::parse("1 120\r\n").unwrap(), ListEntry { id: 1, size: 120 });
+        assert_eq!(ListEntry::parse("2 200 extra info").unwrap(), ListEntry { id: 2, size: 200 });
+
+        for line in ["", "1", "1  120", "a 120", "1 -120", "1 +120", " 1 120", "1\t120"] {
+            assert!(matches!(ListEntry::parse(line), Err(Pop3Error::MalformedLine(_))), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn uidl_entry() {
+        assert_eq!(
+            UidlEntry::parse("1 whqtswO00WBw418f9t5JxYwZ\r\n").unwrap(),
+            UidlEntry { id: 1, uid: "whqtswO00WBw418f9t5JxYwZ".into() }
+        );
+        assert_eq!(UidlEntry::parse(&format!("3 {}", "~".repeat(70))).unwrap().uid.len(), 70);
+
+        let too_long = format!("3 {}", "a".repeat(71));
+        for line in ["", "1", "1 ", "x abc", "1 two words", "1 caf\u{e9}", "1 tab\there", too_long.as_str()] {
+            assert!(matches!(UidlEntry::parse(line), Err(Pop3Error::MalformedLine(_))), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn listing() {
+        let response = Response::new(Bytes::from_static(b"2 messages\r\n1 120\r\n2 200\r\n"));
+        let entries = parse_listing(&response, ListEntry::parse).unwrap();
+        assert_eq!(entries, [ListEntry { id: 1, size: 120 }, ListEntry { id: 2, size: 200 }]);
+
+        let response = Response::new(Bytes::from_static(b"\r\n"));
+        assert!(parse_listing(&response, UidlEntry::parse).unwrap().is_empty());
+
+        let response = Response::new(Bytes::from_static(b"2 QhdPYR:00WBw1Ph7x7\r\n"));
+        assert_eq!(parse_single(&response, UidlEntry::parse).unwrap().uid, "QhdPYR:00WBw1Ph7x7");
+    }
+}
@@ -0,0 +1,408 @@
+//! Delivery of retrieved messages into a [Maildir]
+//!
+//! A message is written to `tmp`, flushed to disk, then moved to `new` under a unique name,
+//! so a crash never leaves a partial message where a mail reader would pick it up.
+//!
+//! The POP3 unique-id of a message can be recorded along with it, for a later run to skip the messages already delivered.
+//! It is recorded after the message is in place: a crash in between leads to a duplicate, never to a loss.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use pop3_client::maildir::{Maildir, UidRecord};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+//! let maildir = Maildir::open("/var/mail/archive")?.record_uids(UidRecord::Sidecar);
+//! let delivered = maildir.delivered_uids()?;
+//!
+//! for entry in client.uidl_all().await? {
+//!     if !delivered.contains(&entry.uid) {
+//!         let message = client.retr(entry.id).await?;
+//!         maildir.deliver(&message, Some(&entry.uid))?;
+//!     }
+//! }
+//! #    Ok(())
+//! # }
+//! ```
+//!
+//! [Maildir]: https://cr.yp.to/proto/maildir.html
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Result;
+
+/// Header field holding the unique-id with [`UidRecord::Header`]
+pub const UID_HEADER: &str = "X-POP3-UID";
+
+/// File of the maildir listing the unique-ids with [`UidRecord::Sidecar`], hidden from mail readers by its leading dot
+pub const UID_SIDECAR: &str = ".pop3-uids";
+
+/// Deliveries of this process, to tell apart the names generated within the same microsecond
+static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Where the unique-id of a delivered message is recorded
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum UidRecord {
+    /// Not recorded
+    #[default]
+    None,
+    /// In an [`UID_HEADER`] field prepended to the message
+    Header,
+    /// In the [`UID_SIDECAR`] file of the maildir, one per line
+    Sidecar,
+}
+
+/// A maildir, that is a directory with `tmp`, `new` and `cur` subdirectories
+#[derive(Debug, Clone)]
+pub struct Maildir {
+    path:   PathBuf,
+    record: UidRecord,
+}
+
+/// A message being written to `tmp`, moved to `new` by [`commit`](Self::commit)
+///
+/// Dropping it without committing removes the partial message.
+#[derive(Debug)]
+pub struct Delivery<'a> {
+    maildir: &'a Maildir,
+    file:    Option<File>,
+    name:    String,
+    uid:     Option<String>,
+}
+
+impl Maildir {
+    /// Open the maildir at `path`, creating it and its subdirectories if needed
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        for dir in ["tmp", "new", "cur"] {
+            fs::create_dir_all(path.join(dir))?;
+        }
+
+        Ok(Self { path, record: UidRecord::None })
+    }
+
+    /// Record the unique-ids of the delivered messages
+    pub fn record_uids(mut self, record: UidRecord) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Root directory of the maildir
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Deliver a whole message, returning its path in `new`
+    ///
+    /// # Errors
+    /// - [`Pop3Error::Io`](crate::Pop3Error::Io) of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the unique-id is not made of printable ASCII characters, as RFC 1939 requires
+    /// - [`Pop3Error::Io`](crate::Pop3Error::Io) if the message could not be written, in which case nothing is delivered
+    pub fn deliver(&self, message: &[u8], uid: Option<&str>) -> Result<PathBuf> {
+        let mut delivery = self.start(uid)?;
+        delivery.write_all(message)?;
+        delivery.commit()
+    }
+
+    /// Start the delivery of a message written piece by piece, e.g. by `retr_to_writer`
+    ///
+    /// # Errors
+    /// - [`Pop3Error::Io`](crate::Pop3Error::Io) of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if the unique-id is not made of printable ASCII characters, as RFC 1939 requires
+    /// - [`Pop3Error::Io`](crate::Pop3Error::Io) if the file could not be created in `tmp`
+    pub fn start(&self, uid: Option<&str>) -> Result<Delivery<'_>> {
+        if let Some(uid) = uid {
+            if uid.is_empty() || !uid.bytes().all(|b| b.is_ascii_graphic()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid unique-id {uid:?}")).into());
+            }
+        }
+
+        let name = unique_name();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.path.join("tmp").join(&name))?;
+
+        if let (UidRecord::Header, Some(uid)) = (self.record, uid) {
+            write!(file, "{UID_HEADER}: {uid}\r\n")?;
+        }
+
+        Ok(Delivery {
+            maildir: self,
+            file:    Some(file),
+            name,
+            uid:     uid.map(String::from),
+        })
+    }
+
+    /// Unique-ids of the messages delivered so far, as recorded with the configured [`UidRecord`]
+    ///
+    /// With [`UidRecord::Header`], the messages in `new` and `cur` are scanned, so the ones removed since are not reported.
+    pub fn delivered_uids(&self) -> Result<HashSet<String>> {
+        match self.record {
+            UidRecord::None    => Ok(HashSet::new()),
+            UidRecord::Sidecar => self.sidecar_uids(),
+            UidRecord::Header  => self.header_uids(),
+        }
+    }
+
+    fn sidecar_uids(&self) -> Result<HashSet<String>> {
+        let file = match File::open(self.path.join(UID_SIDECAR)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut uids = HashSet::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            // A line cut by a crash is incomplete, so it is ignored
+            if let Some(uid) = line.strip_suffix(' ') {
+                uids.insert(uid.to_string());
+            }
+        }
+
+        Ok(uids)
+    }
+
+    fn header_uids(&self) -> Result<HashSet<String>> {
+        let mut uids = HashSet::new();
+
+        for dir in ["new", "cur"] {
+            for entry in fs::read_dir(self.path.join(dir))? {
+                let path = entry?.path();
+
+                if let Some(uid) = header_uid(&path)? {
+                    uids.insert(uid);
+                }
+            }
+        }
+
+        Ok(uids)
+    }
+
+    fn record_sidecar(&self, uid: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.path.join(UID_SIDECAR))?;
+
+        // The trailing space marks a complete line
+        file.write_all(format!("{uid} \n").as_bytes())?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+}
+
+impl Delivery<'_> {
+    /// Flush the message to disk and move it to `new`, returning its path there
+    pub fn commit(mut self) -> Result<PathBuf> {
+        let file = self.file
+            .take()
+            .ok_or_else(|| io::Error::other("delivery already committed"))?;
+        file.sync_all()?;
+        drop(file);
+
+        let tmp = self.maildir.path.join("tmp").join(&self.name);
+        let new = self.maildir.path.join("new").join(&self.name);
+
+        if let Err(e) = fs::rename(&tmp, &new) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+
+        sync_dir(&self.maildir.path.join("new"))?;
+
+        if let (UidRecord::Sidecar, Some(uid)) = (self.maildir.record, &self.uid) {
+            self.maildir.record_sidecar(uid)?;
+        }
+
+        Ok(new)
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("delivery already committed"))
+    }
+}
+
+impl Write for Delivery<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for Delivery<'_> {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(self.maildir.path.join("tmp").join(&self.name));
+        }
+    }
+}
+
+/// A file name unique across hosts, processes and deliveries: `seconds.MmicrosecondsPpidQcounter.host`
+fn unique_name() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    format!(
+        "{}.M{}P{}Q{}.{}",
+        now.as_secs(),
+        now.subsec_micros(),
+        std::process::id(),
+        DELIVERIES.fetch_add(1, Ordering::Relaxed),
+        hostname(),
+    )
+}
+
+/// Name of the host, with the characters that are special in maildir names escaped as the spec requires
+fn hostname() -> String {
+    let name = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| fs::read_to_string("/proc/sys/kernel/hostname"))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".into());
+
+    name.replace('/', "\\057").replace(':', "\\072")
+}
+
+/// The unique-id recorded in the header of a delivered message, looking no further than the header section
+fn header_uid(path: &Path) -> Result<Option<String>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    for line in reader.split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case(UID_HEADER) {
+                return Ok(Some(value.trim().to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Make a rename into the directory durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pop3Error;
+
+    fn maildir(name: &str) -> Maildir {
+        let path = std::env::temp_dir().join(format!("pop3-maildir-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        Maildir::open(path).unwrap()
+    }
+
+    fn count(maildir: &Maildir, dir: &str) -> usize {
+        fs::read_dir(maildir.path().join(dir)).unwrap().count()
+    }
+
+    #[test]
+    fn deliver() {
+        let maildir = maildir("deliver");
+
+        let first = maildir.deliver(b"Subject: one\r\n\r\nbody\r\n", None).unwrap();
+        let second = maildir.deliver(b"Subject: two\r\n\r\nbody\r\n", None).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&first).unwrap(), b"Subject: one\r\n\r\nbody\r\n");
+        assert_eq!(count(&maildir, "new"), 2);
+        assert_eq!(count(&maildir, "tmp"), 0);
+        assert!(maildir.delivered_uids().unwrap().is_empty());
+
+        fs::remove_dir_all(maildir.path()).unwrap();
+    }
+
+    #[test]
+    fn abandoned() {
+        let maildir = maildir("abandoned");
+
+        let mut delivery = maildir.start(None).unwrap();
+        delivery.write_all(b"Subject: partial\r\n").unwrap();
+        assert_eq!(count(&maildir, "tmp"), 1);
+
+        drop(delivery);
+        assert_eq!(count(&maildir, "tmp"), 0);
+        assert_eq!(count(&maildir, "new"), 0);
+
+        assert!(matches!(maildir.start(Some("has space")), Err(Pop3Error::Io(e)) if e.kind() == io::ErrorKind::InvalidInput));
+
+        fs::remove_dir_all(maildir.path()).unwrap();
+    }
+
+    #[test]
+    fn sidecar() {
+        let maildir = maildir("sidecar").record_uids(UidRecord::Sidecar);
+
+        maildir.deliver(b"Subject: one\r\n\r\n", Some("uid-1")).unwrap();
+        maildir.deliver(b"Subject: two\r\n\r\n", Some("uid-2")).unwrap();
+        maildir.deliver(b"Subject: three\r\n\r\n", None).unwrap();
+
+        // A line cut by a crash
+        OpenOptions::new().append(true).open(maildir.path().join(UID_SIDECAR)).unwrap().write_all(b"uid-").unwrap();
+
+        let uids = maildir.delivered_uids().unwrap();
+        assert_eq!(uids, HashSet::from(["uid-1".to_string(), "uid-2".to_string()]));
+
+        fs::remove_dir_all(maildir.path()).unwrap();
+    }
+
+    #[test]
+    fn header() {
+        let maildir = maildir("header").record_uids(UidRecord::Header);
+
+        let path = maildir.deliver(b"Subject: one\r\n\r\nX-POP3-UID: not-a-header\r\n", Some("uid-1")).unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(b"X-POP3-UID: uid-1\r\nSubject: one\r\n"));
+
+        // Messages seen by a reader are moved to cur
+        fs::rename(&path, maildir.path().join("cur").join(format!("{}:2,S", path.file_name().unwrap().to_str().unwrap()))).unwrap();
+        maildir.deliver(b"Subject: two\r\n\r\n", None).unwrap();
+
+        assert_eq!(maildir.delivered_uids().unwrap(), HashSet::from(["uid-1".to_string()]));
+
+        fs::remove_dir_all(maildir.path()).unwrap();
+    }
+
+    #[test]
+    fn names() {
+        assert!(!hostname().contains(['/', ':']));
+        assert_ne!(unique_name(), unique_name());
+    }
+}
@@ -0,0 +1,244 @@
+//! Export of retrieved messages to a single mbox file, in the mboxrd format
+//!
+//! Each message is preceded by a `From ` separator line holding its envelope sender and delivery date,
+//! derived from its header, and lines starting with `From `, after any number of `>`, are quoted with one more `>`,
+//! so that reading the file back restores the messages exactly. Line endings are stored as LF, the convention of mbox files.
+//!
+//! The file is locked exclusively while the [`Mbox`] is open, so concurrent writers taking the same lock wait their turn.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use pop3_client::mbox::Mbox;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+//! let mut mbox = Mbox::open("snapshot.mbox")?;
+//!
+//! for entry in client.list_all().await? {
+//!     mbox.append(&client.retr(entry.id).await?)?;
+//! }
+//!
+//! mbox.sync()?;
+//! #    Ok(())
+//! # }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Date, Headers, Result};
+
+/// Envelope sender of the messages whose header holds no usable address
+const UNKNOWN_SENDER: &str = "MAILER-DAEMON";
+
+/// An mboxrd file open for appending
+#[derive(Debug)]
+pub struct Mbox {
+    file: File,
+    path: PathBuf,
+}
+
+impl Mbox {
+    /// Open the file at `path` for appending, creating it if needed, and lock it
+    ///
+    /// Blocks until the lock is available.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        file.lock()?;
+
+        Ok(Self { file, path })
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a message, as returned by `retr`
+    ///
+    /// # Errors
+    /// - [`Pop3Error::Io`](crate::Pop3Error::Io) if the message could not be written, in which case the file is truncated back to its previous end
+    pub fn append(&mut self, message: &[u8]) -> Result<()> {
+        let entry = entry(message, SystemTime::now());
+        let end = self.file.seek(SeekFrom::End(0))?;
+
+        if let Err(e) = self.file.write_all(&entry) {
+            let _ = self.file.set_len(end);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Flush the appended messages to disk
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// A message with its separator line, quoted and followed by the blank line ending it
+fn entry(message: &[u8], now: SystemTime) -> Vec<u8> {
+    let headers = Headers::parse(message);
+    let mut entry = format!("From {} {}\n", sender(&headers), asctime(delivery_time(&headers, now))).into_bytes();
+    entry.reserve(message.len() + 2);
+
+    let message = message.strip_suffix(b"\n").unwrap_or(message);
+
+    for line in message.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let unquoted = &line[line.iter().take_while(|&&b| b == b'>').count()..];
+
+        if unquoted.starts_with(b"From ") {
+            entry.push(b'>');
+        }
+
+        entry.extend_from_slice(line);
+        entry.push(b'\n');
+    }
+
+    entry.push(b'\n');
+    entry
+}
+
+/// The envelope sender: the `Return-Path`, else the first `From` address
+fn sender(headers: &Headers) -> String {
+    let return_path = headers
+        .get("Return-Path")
+        .map(|path| path.trim().trim_start_matches('<').trim_end_matches('>').to_string());
+
+    return_path
+        .into_iter()
+        .chain(headers.from().into_iter().map(|address| address.email))
+        .find(|address| !address.is_empty() && !address.contains(char::is_whitespace))
+        .unwrap_or_else(|| UNKNOWN_SENDER.into())
+}
+
+/// Seconds since the Unix epoch at which the message was delivered: the date of the topmost `Received` field,
+/// added by the last server, else the `Date` field, else `now`
+fn delivery_time(headers: &Headers, now: SystemTime) -> i64 {
+    let received = headers
+        .get("Received")
+        .and_then(|received| received.rsplit_once(';'))
+        .and_then(|(_, date)| Date::parse(date));
+
+    match received.or_else(|| headers.date()) {
+        Some(date) => date.unix_timestamp(),
+        None => match now.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(e)      => -(e.duration().as_secs() as i64),
+        },
+    }
+}
+
+/// A timestamp in the UTC `asctime` format of separator lines, such as `Fri Oct 16 09:05:00 2026`
+fn asctime(timestamp: i64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{} {} {:2} {:02}:{:02}:{:02} {}",
+        DAYS[days.rem_euclid(7) as usize],
+        MONTHS[usize::from(month) - 1],
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        year,
+    )
+}
+
+/// The date of the proleptic Gregorian calendar a number of days after the Unix epoch
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u8;
+
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        let message = b"From: Alice <alice@example.com>\r\n\r\nFrom here\r\n>From there\r\n>>From: kept\r\n>>From kept\r\n From aside\r\nFrom";
+        let entry = entry(message, UNIX_EPOCH);
+
+        assert_eq!(
+            entry,
+            b"From alice@example.com Thu Jan  1 00:00:00 1970\n\
+              From: Alice <alice@example.com>\n\
+              \n\
+              >From here\n\
+              >>From there\n\
+              >>From: kept\n\
+              >>>From kept\n \
+              From aside\n\
+              From\n\
+              \n"
+        );
+    }
+
+    #[test]
+    fn separator() {
+        let message = b"Received: from mx.example.com by pop.example.com;\r\n Fri, 16 Oct 2026 11:05:09 +0200\r\n\
+            Return-Path: <bounces@example.com>\r\n\
+            From: alice@example.com\r\n\
+            Date: Thu, 15 Oct 2026 23:00:00 +0000\r\n\r\nBody\r\n";
+        assert!(entry(message, UNIX_EPOCH).starts_with(b"From bounces@example.com Fri Oct 16 09:05:09 2026\n"));
+
+        let message = b"Return-Path: <>\r\nFrom: alice@example.com\r\nDate: Thu, 15 Oct 2026 23:00:00 +0000\r\n\r\n";
+        assert!(entry(message, UNIX_EPOCH).starts_with(b"From alice@example.com Thu Oct 15 23:00:00 2026\n"));
+
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(951_782_400);
+        assert!(entry(b"Subject: none\r\n\r\n", now).starts_with(b"From MAILER-DAEMON Tue Feb 29 00:00:00 2000\n"));
+
+        assert_eq!(asctime(-1), "Wed Dec 31 23:59:59 1969");
+    }
+
+    #[test]
+    fn append() {
+        let path = std::env::temp_dir().join(format!("pop3-mbox-{}.mbox", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut mbox = Mbox::open(&path).unwrap();
+        mbox.append(b"From: a@example.com\r\n\r\none\r\n").unwrap();
+        mbox.append(b"From: b@example.com\r\n\r\ntwo\r\n").unwrap();
+        mbox.sync().unwrap();
+
+        // The lock is held until the mbox is dropped
+        let other = File::open(&path).unwrap();
+        assert!(other.try_lock().is_err());
+        drop(mbox);
+        assert!(other.try_lock().is_ok());
+
+        let content = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(content.matches("\nFrom: ").count(), 2);
+        assert!(content.ends_with("\n\ntwo\n\n"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,640 @@
+use base64::Engine;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use bytes::Bytes;
+
+use crate::Headers;
+use crate::headers::{decode_words, to_utf8};
+
+/// Deepest nesting of parts that is parsed, the deeper ones being kept as opaque leaves
+const MAX_DEPTH: usize = 32;
+
+/// Lenient base64, as found in the wild: line breaks, missing padding and trailing bits are accepted
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// A MIME message ([RFC 2045], [RFC 2046]), such as returned by `retr`
+///
+/// Parsing never fails: malformed structures are kept as opaque parts. The raw bodies are slices of the original
+/// [`Bytes`], so only decoding a transfer encoding or a charset copies data.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::result::Result;
+/// # use pop3_client::{AsyncClient, Message, Pop3Error};
+/// #
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Pop3Error> {
+/// # let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+/// let message = Message::parse(client.retr(1).await?);
+///
+/// println!("{}", message.text().unwrap_or_default());
+///
+/// for attachment in message.attachments() {
+///     let name = attachment.filename();
+///     let data = attachment.body();
+/// }
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// [RFC 2045]: https://tools.ietf.org/html/rfc2045
+/// [RFC 2046]: https://tools.ietf.org/html/rfc2046
+#[derive(Debug, Clone)]
+pub struct Message {
+    root: Part,
+}
+
+/// An entity of a MIME message: its header fields and either a body or sub-parts
+#[derive(Debug, Clone)]
+pub struct Part {
+    headers: Headers,
+    body:    Bytes,
+    parts:   Vec<Part>,
+}
+
+/// Value of a `Content-Type` or `Content-Disposition` field, with its parameters
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ContentType {
+    value:  String,
+    params: Vec<(String, String)>,
+}
+
+impl Message {
+    /// Parse a message, keeping slices of `data`
+    pub fn parse(data: Bytes) -> Self {
+        Self { root: Part::parse(data, 0) }
+    }
+
+    /// The top-level entity
+    pub fn root(&self) -> &Part {
+        &self.root
+    }
+
+    /// Header fields of the message
+    pub fn headers(&self) -> &Headers {
+        &self.root.headers
+    }
+
+    /// All the parts, depth-first, starting with the top-level entity
+    pub fn walk(&self) -> impl Iterator<Item = &Part> {
+        self.root.walk()
+    }
+
+    /// The first plain text body, decoded to UTF-8
+    pub fn text(&self) -> Option<String> {
+        self.body_of("text/plain")
+    }
+
+    /// The first HTML body, decoded to UTF-8
+    pub fn html(&self) -> Option<String> {
+        self.body_of("text/html")
+    }
+
+    /// The attached files
+    pub fn attachments(&self) -> impl Iterator<Item = &Part> {
+        self.walk().filter(|part| part.is_attachment())
+    }
+
+    fn body_of(&self, mime_type: &str) -> Option<String> {
+        self.walk()
+            .find(|part| !part.is_attachment() && part.content_type().mime_type() == mime_type)
+            .and_then(Part::text)
+    }
+}
+
+impl Part {
+    fn parse(data: Bytes, depth: usize) -> Self {
+        let (headers, body) = match header_end(&data) {
+            Some((end, start)) => (Headers::parse(&data[..end]), data.slice(start..)),
+            None               => (Headers::parse(&data), Bytes::new()),
+        };
+
+        let mut part = Self { headers, body, parts: Vec::new() };
+
+        if depth >= MAX_DEPTH {
+            return part;
+        }
+
+        let content_type = part.content_type();
+
+        if content_type.main_type() == "multipart" {
+            if let Some(boundary) = content_type.param("boundary") {
+                part.parts = split(&part.body, &boundary)
+                    .into_iter()
+                    .map(|body| Self::parse(body, depth + 1))
+                    .collect();
+            }
+        } else if content_type.mime_type() == "message/rfc822" && part.is_identity() {
+            part.parts = vec![Self::parse(part.body.clone(), depth + 1)];
+        }
+
+        part
+    }
+
+    /// Header fields of the part
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The `Content-Type`, `text/plain` if there is none
+    pub fn content_type(&self) -> ContentType {
+        match self.headers.get("Content-Type") {
+            Some(value) => ContentType::parse(value),
+            None        => ContentType::parse("text/plain; charset=us-ascii"),
+        }
+    }
+
+    /// The `Content-Disposition`, if any
+    pub fn disposition(&self) -> Option<ContentType> {
+        self.headers
+            .get("Content-Disposition")
+            .map(ContentType::parse)
+    }
+
+    /// Sub-parts of a multipart entity, or the encapsulated message of a `message/rfc822` one
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// This part and all its sub-parts, depth-first
+    pub fn walk(&self) -> impl Iterator<Item = &Part> {
+        let mut stack = vec![self];
+
+        std::iter::from_fn(move || {
+            let part = stack.pop()?;
+            stack.extend(part.parts.iter().rev());
+            Some(part)
+        })
+    }
+
+    /// The body as sent, still in its transfer encoding
+    pub fn raw_body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The body with its `Content-Transfer-Encoding` undone; a slice of the message unless it was base64 or quoted-printable
+    pub fn body(&self) -> Bytes {
+        let encoding = self.headers
+            .get("Content-Transfer-Encoding")
+            .map(|e| e.trim().to_ascii_lowercase());
+
+        match encoding.as_deref() {
+            Some("base64")           => Bytes::from(decode_base64(&self.body)),
+            Some("quoted-printable") => Bytes::from(decode_quoted_printable(&self.body)),
+            _                        => self.body.clone(),
+        }
+    }
+
+    /// The decoded body converted to UTF-8 from its charset, for a `text` part
+    ///
+    /// Text in an unknown charset is converted lossily.
+    pub fn text(&self) -> Option<String> {
+        let content_type = self.content_type();
+
+        if content_type.main_type() != "text" {
+            return None;
+        }
+
+        let body = self.body();
+        let charset = content_type.param("charset").unwrap_or_else(|| "us-ascii".into());
+
+        Some(to_utf8(&charset, &body).unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Name of the attached file, from the `Content-Disposition` or else from the `Content-Type`
+    pub fn filename(&self) -> Option<String> {
+        self.disposition()
+            .and_then(|d| d.param("filename"))
+            .or_else(|| self.content_type().param("name"))
+    }
+
+    /// Whether the part is an attached file: it has the `attachment` disposition or a file name
+    pub fn is_attachment(&self) -> bool {
+        if !self.parts.is_empty() || self.content_type().main_type() == "multipart" {
+            return false;
+        }
+
+        self.disposition().is_some_and(|d| d.value == "attachment") || self.filename().is_some()
+    }
+
+    fn is_identity(&self) -> bool {
+        self.headers
+            .get("Content-Transfer-Encoding")
+            .is_none_or(|e| matches!(e.trim().to_ascii_lowercase().as_str(), "7bit" | "8bit" | "binary"))
+    }
+}
+
+impl ContentType {
+    /// Parse a field value such as `text/plain; charset="utf-8"`
+    pub fn parse(value: &str) -> Self {
+        let mut items = split_params(value).into_iter();
+
+        let value = items
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        let params = items
+            .filter_map(|item| {
+                let (name, value) = item.split_once('=')?;
+                Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+            })
+            .collect();
+
+        Self { value, params }
+    }
+
+    /// The `type/subtype`, lowercase
+    pub fn mime_type(&self) -> &str {
+        &self.value
+    }
+
+    /// The type, such as `text`
+    pub fn main_type(&self) -> &str {
+        self.value.split('/').next().unwrap_or_default()
+    }
+
+    /// The subtype, such as `plain`
+    pub fn sub_type(&self) -> &str {
+        self.value.split_once('/').map_or("", |(_, sub)| sub)
+    }
+
+    /// A parameter, joining its [RFC 2231] continuations and decoding its charset, or its [RFC 2047] encoded words
+    ///
+    /// [RFC 2231]: https://tools.ietf.org/html/rfc2231
+    /// [RFC 2047]: https://tools.ietf.org/html/rfc2047
+    pub fn param(&self, name: &str) -> Option<String> {
+        let name = name.to_ascii_lowercase();
+
+        if let Some((_, value)) = self.params.iter().find(|(n, _)| *n == name) {
+            return Some(decode_words(value));
+        }
+
+        if let Some((_, value)) = self.params.iter().find(|(n, _)| n.strip_prefix(name.as_str()) == Some("*")) {
+            return Some(decode_extended(&[(true, value.as_str())]));
+        }
+
+        // Continuations: name*0, name*1*, ...
+        let mut sections: Vec<(usize, bool, &str)> = self.params
+            .iter()
+            .filter_map(|(n, value)| {
+                let section = n.strip_prefix(name.as_str())?.strip_prefix('*')?;
+                let (index, encoded) = match section.strip_suffix('*') {
+                    Some(index) => (index, true),
+                    None        => (section, false),
+                };
+                Some((index.parse().ok()?, encoded, value.as_str()))
+            })
+            .collect();
+
+        if sections.is_empty() {
+            return None;
+        }
+
+        sections.sort_by_key(|(index, ..)| *index);
+
+        let sections: Vec<_> = sections.into_iter().map(|(_, encoded, value)| (encoded, value)).collect();
+
+        Some(decode_extended(&sections))
+    }
+}
+
+/// Position of the end of the header section, and of the start of the body
+fn header_end(data: &[u8]) -> Option<(usize, usize)> {
+    // An empty line at the very start means there are no header fields
+    let mut starts = std::iter::once(0).chain(
+        data.iter()
+            .enumerate()
+            .filter(|(_, &b)| b == b'\n')
+            .map(|(i, _)| i + 1)
+    );
+
+    starts.find_map(|start| match &data[start..] {
+        [b'\n', ..]        => Some((start, start + 1)),
+        [b'\r', b'\n', ..] => Some((start, start + 2)),
+        _                  => None,
+    })
+}
+
+/// Split a multipart body at its boundary delimiters, dropping the preamble and the epilogue
+fn split(body: &Bytes, boundary: &str) -> Vec<Bytes> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut pos = 0;
+
+    while pos < body.len() {
+        let end = body[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(body.len(), |i| pos + i + 1);
+
+        if let Some(rest) = body[pos..end].strip_prefix(delimiter.as_bytes()) {
+            let close = rest.starts_with(b"--");
+            let rest = if close { &rest[2..] } else { rest };
+
+            if rest.iter().all(u8::is_ascii_whitespace) {
+                if let Some(start) = start {
+                    // The line break before the delimiter belongs to it
+                    let mut last = pos;
+                    if last > start && body[last - 1] == b'\n' {
+                        last -= 1;
+                    }
+                    if last > start && body[last - 1] == b'\r' {
+                        last -= 1;
+                    }
+                    parts.push(body.slice(start..last));
+                }
+
+                if close {
+                    return parts;
+                }
+                start = Some(end);
+            }
+        }
+
+        pos = end;
+    }
+
+    // A missing close delimiter ends the last part with the body
+    if let Some(start) = start {
+        parts.push(body.slice(start..));
+    }
+
+    parts
+}
+
+/// Split a field value at the semicolons outside of quotes
+fn split_params(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped      => escaped = false,
+            '\\' if quoted    => escaped = true,
+            '"'               => quoted = !quoted,
+            ';' if !quoted    => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    items.push(&value[start..]);
+    items
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut unquoted = String::with_capacity(quoted.len());
+            let mut escaped = false;
+
+            for c in quoted.chars() {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    c => {
+                        unquoted.push(c);
+                        escaped = false;
+                    }
+                }
+            }
+            unquoted
+        }
+        None => value.to_string(),
+    }
+}
+
+/// Join the sections of an [RFC 2231] parameter, the encoded ones being `charset'language'percent-encoded`
+///
+/// [RFC 2231]: https://tools.ietf.org/html/rfc2231
+fn decode_extended(sections: &[(bool, &str)]) -> String {
+    let mut charset = "us-ascii";
+    let mut bytes = Vec::new();
+
+    for (i, &(encoded, value)) in sections.iter().enumerate() {
+        if !encoded {
+            bytes.extend_from_slice(value.as_bytes());
+            continue;
+        }
+
+        let mut value = value;
+
+        // Only the first section names the charset
+        if i == 0 {
+            let mut fields = value.splitn(3, '\'');
+            if let (Some(set), Some(_), Some(rest)) = (fields.next(), fields.next(), fields.next()) {
+                charset = set;
+                value = rest;
+            }
+        }
+
+        bytes.extend(percent_decode(value));
+    }
+
+    to_utf8(charset, &bytes).unwrap_or_else(|| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(hex)) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+fn hex(digits: &[u8]) -> Option<u8> {
+    u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// Decode base64, skipping line breaks and any other character out of the alphabet
+fn decode_base64(data: &[u8]) -> Vec<u8> {
+    let mut clean: Vec<u8> = data
+        .iter()
+        .copied()
+        .filter(|b| b.is_ascii_alphanumeric() || *b == b'+' || *b == b'/')
+        .collect();
+
+    // A lone trailing character cannot encode anything
+    if clean.len() % 4 == 1 {
+        clean.pop();
+    }
+
+    BASE64.decode(&clean).unwrap_or_default()
+}
+
+/// Decode quoted-printable ([RFC 2045] section 6.7): `=XX` octets, soft line breaks, and trailing whitespace
+///
+/// [RFC 2045]: https://tools.ietf.org/html/rfc2045#section-6.7
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        let (content, newline): (&[u8], &[u8]) = match line {
+            [content @ .., b'\r', b'\n'] => (content, b"\r\n"),
+            [content @ .., b'\n']        => (content, b"\n"),
+            content                      => (content, b""),
+        };
+
+        let content = content.trim_ascii_end();
+        let (content, soft) = match content.strip_suffix(b"=") {
+            Some(content) => (content, true),
+            None          => (content, false),
+        };
+
+        let mut i = 0;
+        while i < content.len() {
+            match (content[i], content.get(i + 1..i + 3).and_then(hex)) {
+                (b'=', Some(b)) => {
+                    decoded.push(b);
+                    i += 3;
+                }
+                (b, _) => {
+                    decoded.push(b);
+                    i += 1;
+                }
+            }
+        }
+
+        if !soft {
+            decoded.extend_from_slice(newline);
+        }
+    }
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"From: Jane <jane@example.com>\r\n\
+        Subject: report\r\n\
+        MIME-Version: 1.0\r\n\
+        Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+        \r\n\
+        This is the preamble.\r\n\
+        --outer\r\n\
+        Content-Type: multipart/alternative; boundary=inner\r\n\
+        \r\n\
+        --inner\r\n\
+        Content-Type: text/plain; charset=iso-8859-1\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Caf=E9 au lait, a long line that is =\r\n\
+        soft broken  \r\n\
+        --inner\r\n\
+        Content-Type: text/html; charset=utf-8\r\n\
+        \r\n\
+        <p>Caf\xc3\xa9</p>\r\n\
+        --inner--\r\n\
+        --outer\r\n\
+        Content-Type: application/pdf; name=\"ignored.pdf\"\r\n\
+        Content-Disposition: attachment;\r\n\
+        \tfilename*0*=utf-8''r%C3%A9;\r\n\
+        \tfilename*1=sum.pdf\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        JVBERi0x\r\n\
+        LjQ=\r\n\
+        --outer\r\n\
+        Content-Type: message/rfc822\r\n\
+        \r\n\
+        Subject: forwarded\r\n\
+        \r\n\
+        Inner body\r\n\
+        --outer--\r\n\
+        Epilogue\r\n";
+
+    #[test]
+    fn structure() {
+        let data = Bytes::from_static(MESSAGE);
+        let message = Message::parse(data.clone());
+
+        assert_eq!(message.headers().subject().as_deref(), Some("report"));
+
+        let types: Vec<_> = message.walk().map(|p| p.content_type().mime_type().to_string()).collect();
+        assert_eq!(types, [
+            "multipart/mixed", "multipart/alternative", "text/plain", "text/html",
+            "application/pdf", "message/rfc822", "text/plain",
+        ]);
+
+        let forwarded = &message.root().parts()[2].parts()[0];
+        assert_eq!(forwarded.headers().subject().as_deref(), Some("forwarded"));
+
+        // Bodies are slices of the message
+        let html = message.walk().nth(3).unwrap();
+        assert_eq!(html.raw_body(), &b"<p>Caf\xc3\xa9</p>"[..]);
+        assert!(data.as_ptr_range().contains(&html.raw_body().as_ptr()));
+    }
+
+    #[test]
+    fn bodies() {
+        let message = Message::parse(Bytes::from_static(MESSAGE));
+
+        assert_eq!(message.text().as_deref(), Some("Café au lait, a long line that is soft broken"));
+        assert_eq!(message.html().as_deref(), Some("<p>Café</p>"));
+
+        let attachments: Vec<_> = message.attachments().collect();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].filename().as_deref(), Some("résum.pdf"));
+        assert_eq!(attachments[0].content_type().mime_type(), "application/pdf");
+        assert_eq!(attachments[0].body(), &b"%PDF-1.4"[..]);
+    }
+
+    #[test]
+    fn not_multipart() {
+        let message = Message::parse(Bytes::from_static(b"Subject: plain\n\nHello\n"));
+        assert_eq!(message.text().as_deref(), Some("Hello\n"));
+        assert_eq!(message.attachments().count(), 0);
+
+        // No header section, no close delimiter
+        let message = Message::parse(Bytes::from_static(b"\r\nbody"));
+        assert_eq!(message.root().raw_body(), &b"body"[..]);
+
+        let data = b"Content-Type: multipart/mixed; boundary=b\r\n\r\n--b\r\n\r\nunterminated";
+        let message = Message::parse(Bytes::from_static(data));
+        assert_eq!(message.text().as_deref(), Some("unterminated"));
+    }
+
+    #[test]
+    fn params() {
+        let content_type = ContentType::parse("Text/Plain; Charset=\"UTF-8\"; name*=utf-8'en'%E2%82%AC.txt; title=\"a \\\"b\\\"; c\"");
+        assert_eq!(content_type.mime_type(), "text/plain");
+        assert_eq!(content_type.sub_type(), "plain");
+        assert_eq!(content_type.param("charset").as_deref(), Some("UTF-8"));
+        assert_eq!(content_type.param("name").as_deref(), Some("€.txt"));
+        assert_eq!(content_type.param("title").as_deref(), Some("a \"b\"; c"));
+        assert_eq!(content_type.param("missing"), None);
+    }
+
+    #[test]
+    fn transfer_encodings() {
+        assert_eq!(decode_base64(b"aGVs\r\nbG8"), b"hello");
+        assert_eq!(decode_quoted_printable(b"a=3Db=\nc =XY\n"), b"a=bc =XY\n");
+    }
+}
@@ -0,0 +1,598 @@
+//! Runtime independent POP3 protocol core.
+//!
+//! [`Protocol`] encodes the commands and turns the bytes received from the server into [`Event`]s,
+//! without doing any I/O itself. The clients are thin drivers moving bytes between it and a socket.
+
+use std::collections::VecDeque;
+
+use bytes::{Buf, Bytes, BytesMut, BufMut};
+
+use crate::{Command, Pop3Error, Response, Result};
+
+/// Longest line of a reply accepted by default, in octets
+pub(crate) const MAX_LINE: usize = 1 << 20;
+
+/// Largest multiline reply accepted by default, in octets
+pub(crate) const MAX_RESPONSE: usize = 128 << 20;
+
+/// What the reply to a sent command looks like
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Expect {
+    /// A status line only
+    Single,
+    /// A status line, followed by the dot-terminated lines on success
+    Multi,
+    /// A status line or a `+ ` continuation of the `AUTH` exchange
+    Challenge,
+    /// A status line, followed by the dot-terminated lines on success, handed out in [`Chunk`]s
+    Stream,
+}
+
+impl Expect {
+    fn of(cmd: &Command<'_>) -> Self {
+        match cmd {
+            Command::Auth { .. } | Command::AuthResponse { .. } => Self::Challenge,
+            cmd if cmd.is_response_multiline() => Self::Multi,
+            _ => Self::Single,
+        }
+    }
+}
+
+/// A complete reply of the server
+#[derive(Debug)]
+pub(crate) enum Event {
+    /// Positive reply: the status line text, followed by the unstuffed lines of a multiline reply
+    Response(Response),
+    /// Base64 encoded server challenge of the `AUTH` exchange
+    Continuation(Bytes),
+}
+
+/// A piece of a streamed multiline reply
+#[derive(Debug)]
+pub(crate) enum Chunk {
+    /// Unstuffed data, not necessarily ending at a line boundary
+    Data(Bytes),
+    /// The terminating line has been received
+    End,
+}
+
+/// A reply the server is yet to send
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    expect:   Expect,
+    /// Name of the command the reply answers
+    command:  &'static str,
+    /// Largest size of the multiline reply
+    max_size: usize,
+}
+
+enum State {
+    /// Waiting for the status line
+    Status,
+    /// Collecting the lines of a multiline reply
+    Body(BytesMut),
+    /// Handing out the lines of a streamed multiline reply, the flag tells if the data starts a new line
+    Stream(bool),
+}
+
+pub(crate) struct Protocol {
+    buffer:  BytesMut,
+    /// Length of the buffer prefix known to contain no line end
+    scanned: usize,
+    pending: VecDeque<Pending>,
+    state:   State,
+    max_line:     usize,
+    max_response: usize,
+    /// A reply exceeded the limits, or was not received in time, so the session can not continue
+    closed:  bool,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::new(MAX_LINE, MAX_RESPONSE)
+    }
+}
+
+impl Protocol {
+    /// Protocol accepting lines of up to `max_line` octets, and multiline replies of up to `max_response` octets
+    pub fn new(max_line: usize, max_response: usize) -> Self {
+        Self {
+            buffer:  BytesMut::new(),
+            scanned: 0,
+            pending: VecDeque::new(),
+            state:   State::Status,
+            max_line,
+            max_response,
+            closed:  false,
+        }
+    }
+
+    /// Encode the command to be written to the server, and expect its reply
+    pub fn encode(&mut self, cmd: &Command<'_>) -> Vec<u8> {
+        self.encode_limited(cmd, self.max_response)
+    }
+
+    /// Encode the command to be written to the server, and expect its reply of up to `max_size` octets
+    pub fn encode_limited(&mut self, cmd: &Command<'_>, max_size: usize) -> Vec<u8> {
+        self.expect(Expect::of(cmd), cmd, max_size)
+    }
+
+    /// Encode the command to be written to the server, and expect its multiline reply to be streamed with [`poll_chunk`](Self::poll_chunk)
+    ///
+    /// The size of the streamed data is not limited, as it is not kept.
+    pub fn encode_stream(&mut self, cmd: &Command<'_>) -> Vec<u8> {
+        self.expect(Expect::Stream, cmd, usize::MAX)
+    }
+
+    fn expect(&mut self, expect: Expect, cmd: &Command<'_>, max_size: usize) -> Vec<u8> {
+        self.pending.push_back(Pending { expect, command: cmd.name(), max_size });
+        cmd.to_request().into_bytes()
+    }
+
+    /// Name of the command whose reply is awaited
+    pub fn awaiting(&self) -> &'static str {
+        self.pending.front().map_or("", |p| p.command)
+    }
+
+    /// A reply exceeded the limits or timed out, and the session is over
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Add bytes received from the server
+    pub fn feed(&mut self, data: &[u8]) {
+        if !self.closed {
+            self.buffer.extend_from_slice(data);
+        }
+    }
+
+    /// Drop the received but not yet parsed bytes
+    ///
+    /// Used when upgrading to TLS, so nothing sent in plaintext can be taken as a part of the protected session.
+    #[cfg_attr(not(feature = "with-rustls"), allow(dead_code))]
+    pub fn discard(&mut self) {
+        self.buffer.clear();
+        self.scanned = 0;
+    }
+
+    /// Parse the next reply, if it has been received completely
+    ///
+    /// Returns `None` if more data is needed, or if no reply is expected.
+    pub fn poll(&mut self) -> Option<Result<Event>> {
+        if self.closed {
+            return self.pending.front().map(|_| Err(Pop3Error::ConnectionClosed));
+        }
+
+        // The rest of an abandoned stream is skipped
+        while let State::Stream(_) = self.state {
+            self.poll_chunk()?;
+        }
+
+        loop {
+            let Pending { expect, command, max_size } = *self.pending.front()?;
+
+            let line = match self.next_line() {
+                Ok(line) => line?,
+                Err(e)   => return Some(Err(e)),
+            };
+
+            match &mut self.state {
+                State::Stream(_) => unreachable!("streams are handled by poll_chunk"),
+                State::Body(body) if body.len() + line.len() > max_size => {
+                    return Some(Err(self.close(command, max_size)));
+                }
+                State::Body(body) => match unstuff(&line) {
+                    Some(line) => body.put(line),
+                    None => {
+                        let body = std::mem::take(body).freeze();
+                        self.finish();
+                        return Some(Ok(Event::Response(Response::new(body))));
+                    }
+                },
+                State::Status => {
+                    if let Some(status) = line.strip_prefix(b"+OK") {
+                        let text = status.strip_prefix(b" ").unwrap_or(status);
+
+                        if expect == Expect::Multi {
+                            self.state = State::Body(BytesMut::from(text));
+                            continue;
+                        }
+
+                        if expect == Expect::Stream {
+                            self.state = State::Stream(true);
+                            return Some(Ok(Event::Response(Response::new(Bytes::copy_from_slice(text)))));
+                        }
+
+                        self.finish();
+                        return Some(Ok(Event::Response(Response::new(Bytes::copy_from_slice(text)))));
+                    }
+
+                    self.finish();
+
+                    if expect == Expect::Challenge {
+                        if let Some(challenge) = line.strip_prefix(b"+") {
+                            return Some(Ok(Event::Continuation(Bytes::copy_from_slice(challenge.trim_ascii()))));
+                        }
+                    }
+
+                    return Some(Err(error_response(&line)));
+                }
+            }
+        }
+    }
+
+    /// Take the next piece of the streamed reply, once its status line has been polled
+    ///
+    /// The data is handed out as soon as it is received, without waiting for the line ends, so the memory use does not depend on the line length.
+    /// Returns `None` if more data is needed, or if no stream is in progress.
+    pub fn poll_chunk(&mut self) -> Option<Chunk> {
+        let State::Stream(line_start) = self.state else {
+            return None;
+        };
+
+        if line_start {
+            match &self.buffer[..] {
+                [b'.', b'\n', ..]        => return Some(self.end_stream(2)),
+                [b'.', b'\r', b'\n', ..] => return Some(self.end_stream(3)),
+                // The terminator can not be told from a stuffed line yet
+                [b'.'] | [b'.', b'\r']   => return None,
+                [b'.', ..] => {
+                    self.buffer.advance(1);
+                    self.state = State::Stream(false);
+                }
+                _ => {}
+            }
+        }
+
+        // Data up to the next line starting with a dot is passed as is
+        let mut end = 0;
+        let mut line_start = matches!(self.state, State::Stream(true));
+
+        while end < self.buffer.len() {
+            if line_start && end > 0 && self.buffer[end] == b'.' {
+                break;
+            }
+
+            match self.buffer[end..].iter().position(|&b| b == b'\n') {
+                Some(pos) => {
+                    end += pos + 1;
+                    line_start = true;
+                }
+                None => {
+                    end = self.buffer.len();
+                    line_start = false;
+                }
+            }
+        }
+
+        if end == 0 {
+            return None;
+        }
+
+        self.scanned = 0;
+        self.state = State::Stream(line_start);
+
+        Some(Chunk::Data(self.buffer.split_to(end).freeze()))
+    }
+
+    fn end_stream(&mut self, terminator: usize) -> Chunk {
+        self.buffer.advance(terminator);
+        self.scanned = 0;
+        self.finish();
+        Chunk::End
+    }
+
+    fn finish(&mut self) {
+        self.state = State::Status;
+        self.pending.pop_front();
+    }
+
+    /// End the session, freeing what has been received
+    ///
+    /// Used when a reply did not arrive in time: read later, it would be taken for the reply to the next command.
+    pub fn abort(&mut self) {
+        self.closed = true;
+        self.buffer = BytesMut::new();
+        self.scanned = 0;
+        self.state = State::Status;
+    }
+
+    /// End the session because of a reply exceeding the limit
+    fn close(&mut self, command: &'static str, limit: usize) -> Pop3Error {
+        self.abort();
+
+        Pop3Error::ResponseTooLarge { command, limit }
+    }
+
+    fn next_line(&mut self) -> Result<Option<BytesMut>> {
+        match self.buffer[self.scanned..].iter().position(|&b| b == b'\n') {
+            Some(pos) if self.scanned + pos >= self.max_line => Err(self.close(self.awaiting(), self.max_line)),
+            Some(pos) => {
+                let end = self.scanned + pos + 1;
+                self.scanned = 0;
+                Ok(Some(self.buffer.split_to(end)))
+            }
+            None if self.buffer.len() > self.max_line => Err(self.close(self.awaiting(), self.max_line)),
+            None => {
+                self.scanned = self.buffer.len();
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Undo the byte-stuffing of a multiline response line ([RFC 1939] section 3)
+///
+/// Returns `None` for the terminating line, which some servers send with a bare LF.
+///
+/// [RFC 1939]: https://tools.ietf.org/html/rfc1939#section-3
+fn unstuff(line: &[u8]) -> Option<&[u8]> {
+    match line {
+        b".\r\n" | b".\n" => None,
+        [b'.', rest @ ..] => Some(rest),
+        _ => Some(line),
+    }
+}
+
+/// Convert a negative status line into an error
+fn error_response(line: &[u8]) -> Pop3Error {
+    let text = line.strip_prefix(b"-ERR").unwrap_or(line);
+
+    match std::str::from_utf8(text) {
+        Ok(v)  => Pop3Error::server(v),
+        Err(e) => Pop3Error::InvalidString(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseCode;
+
+    /// Byte-stuff a message and frame it as a multiline response, the way a server does
+    fn transcript(message: &[u8]) -> Vec<u8> {
+        let mut data = b"+OK message follows\r\n".to_vec();
+
+        for line in message.split_inclusive(|&b| b == b'\n') {
+            if line.starts_with(b".") {
+                data.push(b'.');
+            }
+            data.extend_from_slice(line);
+        }
+
+        data.extend_from_slice(b".\r\n");
+        data
+    }
+
+    fn response(event: Option<Result<Event>>) -> Bytes {
+        match event {
+            Some(Ok(Event::Response(r))) => r.raw().clone(),
+            e => panic!("unexpected event {e:?}"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = b"Subject: dots\r\n\r\n.\r\n..\r\n.leading dot\r\ntrailing dot.\r\n";
+
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Retr { id: 1 });
+        protocol.feed(&transcript(message));
+
+        assert_eq!(response(protocol.poll()), [&b"message follows\r\n"[..], message].concat());
+        assert!(protocol.poll().is_none());
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let message = b"line 1\r\n.line 2\r\n";
+
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Retr { id: 1 });
+
+        let data = transcript(message);
+        let (last, data) = data.split_last().unwrap();
+
+        for byte in data {
+            protocol.feed(&[*byte]);
+            assert!(protocol.poll().is_none());
+        }
+
+        protocol.feed(&[*last]);
+        assert_eq!(response(protocol.poll()), [&b"message follows\r\n"[..], message].concat());
+    }
+
+    #[test]
+    fn bare_lf_terminator() {
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::List { id: None });
+        protocol.encode(&Command::Stat);
+        protocol.feed(b"+OK\nline 1\n..line 2\n.\n+OK 2 320\n");
+
+        assert_eq!(response(protocol.poll()), &b"\nline 1\n.line 2\n"[..]);
+        assert_eq!(response(protocol.poll()), &b"2 320\n"[..]);
+    }
+
+    #[test]
+    fn single_line() {
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Greet);
+        protocol.feed(b"+OK POP3 server ready\r\n.\r\n");
+
+        assert_eq!(response(protocol.poll()), &b"POP3 server ready\r\n"[..]);
+        // Nothing is expected, so nothing is parsed
+        assert!(protocol.poll().is_none());
+    }
+
+    #[test]
+    fn error_response() {
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Retr { id: 1 });
+        protocol.feed(b"-ERR no such message\r\n");
+
+        assert!(matches!(protocol.poll(), Some(Err(Pop3Error::Server { code: None, text })) if text == "no such message"));
+
+        protocol.encode(&Command::Pass { data: "secret" });
+        protocol.feed(b"-ERR [AUTH] invalid password\r\n");
+
+        assert!(matches!(protocol.poll(), Some(Err(Pop3Error::Server { code: Some(ResponseCode::Auth), text })) if text == "invalid password"));
+    }
+
+    #[test]
+    fn continuation() {
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Auth { mechanism: "LOGIN", initial: None });
+        protocol.feed(b"+ VXNlcm5hbWU6\r\n");
+
+        assert!(matches!(protocol.poll(), Some(Ok(Event::Continuation(c))) if c == "VXNlcm5hbWU6"));
+
+        protocol.encode(&Command::AuthResponse { data: "dXNlcg==" });
+        protocol.feed(b"+OK\r\n");
+        assert_eq!(response(protocol.poll()), &b"\r\n"[..]);
+
+        // Only AUTH can be continued
+        protocol.encode(&Command::Noop);
+        protocol.feed(b"+ what?\r\n");
+        assert!(matches!(protocol.poll(), Some(Err(_))));
+    }
+
+    #[test]
+    fn awaiting() {
+        let mut protocol = Protocol::default();
+        assert_eq!(protocol.awaiting(), "");
+
+        protocol.encode(&Command::Greet);
+        protocol.encode(&Command::Retr { id: 1 });
+        assert_eq!(protocol.awaiting(), "greeting");
+
+        protocol.feed(b"+OK POP3 server ready\r\n");
+        response(protocol.poll());
+        assert_eq!(protocol.awaiting(), "RETR");
+    }
+
+    #[test]
+    fn discard() {
+        let mut protocol = Protocol::default();
+        protocol.encode(&Command::Stls);
+        protocol.feed(b"+OK Begin TLS\r\n-ERR injected\r\n");
+        assert_eq!(response(protocol.poll()), &b"Begin TLS\r\n"[..]);
+
+        protocol.discard();
+        protocol.encode(&Command::Noop);
+        protocol.feed(b"+OK\r\n");
+        assert_eq!(response(protocol.poll()), &b"\r\n"[..]);
+    }
+
+    /// Collect the streamed reply, feeding the transcript in pieces of the given size
+    fn stream(protocol: &mut Protocol, data: &[u8], piece: usize) -> Vec<u8> {
+        let mut message = Vec::new();
+        let mut pieces = data.chunks(piece);
+        let mut status = false;
+
+        loop {
+            if !status {
+                match protocol.poll() {
+                    Some(event) => {
+                        response(Some(event));
+                        status = true;
+                    }
+                    None => protocol.feed(pieces.next().expect("status line")),
+                }
+                continue;
+            }
+
+            match protocol.poll_chunk() {
+                Some(Chunk::Data(data)) => message.extend_from_slice(&data),
+                Some(Chunk::End) => return message,
+                None => protocol.feed(pieces.next().expect("terminator")),
+            }
+        }
+    }
+
+    #[test]
+    fn streamed() {
+        let message = b"Subject: dots\r\n\r\n.\r\n..\r\n.leading dot\r\ntrailing dot.\r\n.\r\r\n";
+        let data = transcript(message);
+
+        for piece in [1, 2, 3, 7, data.len()] {
+            let mut protocol = Protocol::default();
+            protocol.encode_stream(&Command::Retr { id: 1 });
+            assert_eq!(stream(&mut protocol, &data, piece), message, "pieces of {piece}");
+        }
+    }
+
+    #[test]
+    fn streamed_bare_lf() {
+        let mut protocol = Protocol::default();
+        protocol.encode_stream(&Command::Retr { id: 1 });
+        assert_eq!(stream(&mut protocol, b"+OK\nline 1\n..line 2\n.\n", 1), b"line 1\n.line 2\n");
+    }
+
+    #[test]
+    fn streamed_error() {
+        let mut protocol = Protocol::default();
+        protocol.encode_stream(&Command::Retr { id: 1 });
+        protocol.feed(b"-ERR no such message\r\n");
+
+        assert!(matches!(protocol.poll(), Some(Err(_))));
+        assert!(protocol.poll_chunk().is_none());
+    }
+
+    #[test]
+    fn streamed_abandoned() {
+        let mut protocol = Protocol::default();
+        protocol.encode_stream(&Command::Retr { id: 1 });
+        protocol.feed(b"+OK\r\nline 1\r\n");
+        response(protocol.poll());
+        assert!(matches!(protocol.poll_chunk(), Some(Chunk::Data(_))));
+
+        protocol.encode(&Command::Stat);
+        protocol.feed(b"line 2\r\n.");
+        assert!(protocol.poll().is_none());
+
+        protocol.feed(b"\r\n+OK 2 320\r\n");
+        assert_eq!(response(protocol.poll()), &b"2 320\r\n"[..]);
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut protocol = Protocol::new(16, MAX_RESPONSE);
+        protocol.encode(&Command::Stat);
+        protocol.feed(b"+OK 2 320 and a lot more");
+
+        assert!(matches!(protocol.poll(), Some(Err(Pop3Error::ResponseTooLarge { command: "STAT", limit: 16 }))));
+        assert!(protocol.is_closed());
+
+        protocol.encode(&Command::Noop);
+        protocol.feed(b"+OK\r\n");
+        assert!(matches!(protocol.poll(), Some(Err(Pop3Error::ConnectionClosed))));
+    }
+
+    #[test]
+    fn response_too_large() {
+        let message = b"line 1\r\nline 2\r\nline 3\r\n";
+
+        let mut protocol = Protocol::new(MAX_LINE, 16);
+        protocol.encode(&Command::Retr { id: 1 });
+        protocol.feed(&transcript(message));
+
+        assert!(matches!(protocol.poll(), Some(Err(Pop3Error::ResponseTooLarge { command: "RETR", limit: 16 }))));
+        assert!(protocol.is_closed());
+
+        let mut protocol = Protocol::new(MAX_LINE, 16);
+        protocol.encode_limited(&Command::Retr { id: 1 }, 64);
+        protocol.feed(&transcript(message));
+
+        assert_eq!(response(protocol.poll()), &b"message follows\r\nline 1\r\nline 2\r\nline 3\r\n"[..]);
+    }
+
+    #[test]
+    fn unstuffing() {
+        assert_eq!(unstuff(b".\r\n"), None);
+        assert_eq!(unstuff(b".\n"), None);
+        assert_eq!(unstuff(b"..\r\n"), Some(&b".\r\n"[..]));
+        assert_eq!(unstuff(b"..signature\r\n"), Some(&b".signature\r\n"[..]));
+        assert_eq!(unstuff(b"text.\r\n"), Some(&b"text.\r\n"[..]));
+        assert_eq!(unstuff(b"\r\n"), Some(&b"\r\n"[..]));
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::{Read, Write};
+use std::thread;
+
+use bytes::Bytes;
+
+use super::{is_transient, Backoff, Numbering, Resilient};
+use crate::{Headers, ListEntry, Pop3Error, Response, Result, SyncClient, UidlEntry};
+
+impl<S, F> Resilient<SyncClient<S>, F>
+where
+    S: Read + Write,
+    F: FnMut() -> Result<SyncClient<S>>,
+{
+    /// Open a session with `connect`, which returns an authorized client and is called again to reconnect
+    ///
+    /// Failed attempts are retried as after a connection loss. Errors other than transport failures and temporary refusals,
+    /// e.g. rejected credentials, are returned at once.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{Pop3Error, SyncClient};
+    /// use pop3_client::reconnect::{Backoff, Resilient};
+    ///
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// let connect = || {
+    ///     let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    ///     client.login("sweet_username", "very_secret_password")?;
+    ///     Ok(client)
+    /// };
+    ///
+    /// let mut client = Resilient::connect(connect, Backoff::default())?;
+    /// let message = client.retr(1)?;
+    /// client.quit()?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn connect(mut connect: F, backoff: Backoff) -> Result<Self> {
+        let (client, entries) = open(&mut connect, &backoff, false)?;
+        Ok(Self::new(client, connect, backoff, &entries))
+    }
+
+    /// Unique ids of the messages still in the maildrop, see [`SyncClient::uidl_all`]
+    pub fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        let entries = self.retry(|client, _| client.uidl_all())?;
+        Ok(self.uidl_entries(&entries))
+    }
+
+    /// Scan listing of the messages still in the maildrop, see [`SyncClient::list_all`]
+    pub fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        let entries = self.retry(|client, _| client.list_all())?;
+        Ok(self.list_entries(entries))
+    }
+
+    /// Scan listing of one message, see [`SyncClient::list_one`]
+    pub fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        let entry = self.retry(|client, numbering| client.list_one(numbering.current(id)?))?;
+        Ok(ListEntry { id, size: entry.size })
+    }
+
+    /// Full content of a message, see [`SyncClient::retr`]
+    pub fn retr(&mut self, id: u64) -> Result<Bytes> {
+        self.retry(|client, numbering| client.retr(numbering.current(id)?))
+    }
+
+    /// Headers and first lines of a message, see [`SyncClient::top`]
+    pub fn top(&mut self, id: u64, lines: u64) -> Result<Response> {
+        self.retry(|client, numbering| client.top(numbering.current(id)?, lines))
+    }
+
+    /// Parsed header section of a message, see [`SyncClient::headers`]
+    pub fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.retry(|client, numbering| client.headers(numbering.current(id)?))
+    }
+
+    /// Keep the connection alive, reconnecting if it was lost, see [`SyncClient::noop`]
+    pub fn noop(&mut self) -> Result<()> {
+        self.retry(|client, _| client.noop())
+    }
+
+    /// Mark a message as deleted, see [`SyncClient::dele`]
+    ///
+    /// # Errors
+    /// - [`Pop3Error::DeletionsLost`] if the connection is lost, listing this message along with the others marked in the session.
+    ///   The client is reconnected, but the deletions are not sent again.
+    pub fn dele(&mut self, id: u64) -> Result<Response> {
+        if self.client.is_none() {
+            self.reconnect()?;
+        }
+
+        let result = match self.client.as_mut() {
+            Some(client) => self.numbering.current(id).and_then(|current| client.dele(current)),
+            None => Err(Pop3Error::ConnectionClosed),
+        };
+
+        match result {
+            Ok(response) => {
+                self.deleted.push(id);
+                Ok(response)
+            }
+            Err(e) if e.is_connection_lost() => {
+                // Reported as lost by the reconnection
+                self.deleted.push(id);
+                self.client = None;
+                self.reconnect()?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Unmark the messages marked as deleted, see [`SyncClient::rset`]
+    pub fn rset(&mut self) -> Result<Response> {
+        // Losing the deletions is what is asked, so they are not reported if the connection is lost
+        self.deleted.clear();
+        self.retry(|client, _| client.rset())
+    }
+
+    /// Enter the UPDATE state, in which the server removes the messages marked as deleted, and end the session
+    ///
+    /// The command is not retried, as the server may have committed the deletions before the connection was lost.
+    pub fn quit(self) -> Result<()> {
+        match self.client {
+            Some(client) => client.quit(),
+            None if self.deleted.is_empty() => Ok(()),
+            None => Err(Pop3Error::DeletionsLost { ids: self.deleted }),
+        }
+    }
+
+    /// Run `command`, reconnecting and running it again as long as the connection fails
+    fn retry<T, R>(&mut self, mut command: R) -> Result<T>
+    where
+        R: FnMut(&mut SyncClient<S>, &Numbering) -> Result<T>,
+    {
+        let mut retries = 0;
+
+        loop {
+            if self.client.is_none() {
+                self.reconnect()?;
+            }
+
+            let client = self.client.as_mut().ok_or(Pop3Error::ConnectionClosed)?;
+
+            match command(client, &self.numbering) {
+                Err(e) if e.is_connection_lost() => {
+                    self.client = None;
+
+                    if retries == self.backoff.attempts {
+                        return Err(e);
+                    }
+
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let (client, entries) = open(&mut self.connect, &self.backoff, true)?;
+        self.reconnected(client, &entries)
+    }
+}
+
+/// Connect and list the unique-ids, retrying with `backoff`, after a first delay when `reconnecting`
+fn open<S, F>(connect: &mut F, backoff: &Backoff, reconnecting: bool) -> Result<(SyncClient<S>, Vec<UidlEntry>)>
+where
+    S: Read + Write,
+    F: FnMut() -> Result<SyncClient<S>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        if reconnecting || attempt > 0 {
+            thread::sleep(backoff.delay(attempt));
+        }
+
+        let result = connect().and_then(|mut client| Ok((client.uidl_all()?, client)));
+
+        match result {
+            Ok((entries, client)) => return Ok((client, entries)),
+            Err(e) if is_transient(&e) && attempt + 1 < backoff.attempts => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Sessions surviving the loss of their connection
+//!
+//! A [`Resilient`] client reconnects when the connection to the server fails, waiting longer after each failed attempt,
+//! authenticates again, and retries the command that failed, as long as sending it twice is harmless.
+//!
+//! Message numbers are only valid within a session, so the unique-ids of the messages are listed when connecting
+//! and the numbers of this first session are translated through them in the following ones.
+//! Messages arrived since the first session have no number there, so they are not visible.
+//!
+//! `DELE` is never retried: the deletions of a lost session are not committed, and the command following
+//! a reconnection fails with [`Pop3Error::DeletionsLost`] listing them, for the caller to issue them again if it wishes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use pop3_client::reconnect::{Backoff, Resilient};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! let connect = || async {
+//!     let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+//!     client.login("sweet_username", "very_secret_password").await?;
+//!     Ok::<_, Pop3Error>(client)
+//! };
+//!
+//! let mut client = Resilient::connect_async(connect, Backoff::default()).await?;
+//!
+//! for entry in client.uidl_all().await? {
+//!     let message = client.retr(entry.id).await?;
+//! }
+//!
+//! client.quit().await?;
+//! #    Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::{ListEntry, Pop3Error, ResponseCode, Result, UidlEntry};
+
+#[cfg(feature = "runtime-sync")]
+mod blocking;
+
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
+
+/// Delays between connection attempts: exponential, capped, and randomized so that many clients do not retry in step
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Backoff {
+    /// Delay before the first reconnection attempt
+    pub initial:  Duration,
+    /// Longest delay between two attempts
+    pub max:      Duration,
+    /// Connection attempts before giving up a reconnection, and reconnections before giving up a command
+    pub attempts: u32,
+}
+
+/// A client reconnecting when its connection fails, wrapping a [`SyncClient`](crate::SyncClient) or an [`AsyncClient`](crate::AsyncClient)
+///
+/// `connect` returns a new authorized client, see [`Resilient::connect`] and [`Resilient::connect_async`].
+pub struct Resilient<C, F> {
+    client:    Option<C>,
+    connect:   F,
+    backoff:   Backoff,
+    numbering: Numbering,
+    /// Messages marked as deleted in the current session
+    deleted:   Vec<u64>,
+}
+
+/// Translation of the message numbers of the first session into those of the current one
+#[derive(Debug)]
+struct Numbering {
+    uids:    HashMap<u64, String>,
+    current: HashMap<String, u64>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial:  Duration::from_millis(500),
+            max:      Duration::from_secs(30),
+            attempts: 5,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the attempt `attempt`, counted from 0: the initial delay doubled at each attempt,
+    /// capped, then shortened by a random fraction of up to a half
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let jitter = hasher.finish() as f64 / u64::MAX as f64;
+
+        delay.mul_f64(1.0 - jitter / 2.0)
+    }
+}
+
+/// Whether connecting again may succeed: after a transport failure, or a temporary refusal such as a maildrop still locked by the lost session
+fn is_transient(error: &Pop3Error) -> bool {
+    error.is_connection_lost() || error.code().is_some_and(ResponseCode::is_temporary)
+}
+
+impl<C, F> Resilient<C, F> {
+    fn new(client: C, connect: F, backoff: Backoff, entries: &[UidlEntry]) -> Self {
+        Self {
+            client:    Some(client),
+            connect,
+            backoff,
+            numbering: Numbering::new(entries),
+            deleted:   Vec::new(),
+        }
+    }
+
+    /// The current client, unless the last reconnection failed
+    pub fn client(&mut self) -> Option<&mut C> {
+        self.client.as_mut()
+    }
+
+    /// The current client, unless the last reconnection failed
+    pub fn into_inner(self) -> Option<C> {
+        self.client
+    }
+
+    /// Switch to a new session, reporting the deletions lost with the previous one
+    fn reconnected(&mut self, client: C, entries: &[UidlEntry]) -> Result<()> {
+        self.client = Some(client);
+        self.numbering.remap(entries);
+
+        if self.deleted.is_empty() {
+            return Ok(());
+        }
+
+        Err(Pop3Error::DeletionsLost { ids: std::mem::take(&mut self.deleted) })
+    }
+
+    /// The messages of a unique-id listing of the current session, numbered as in the first one
+    ///
+    /// The messages marked as deleted are not listed, but keep their number, as `RSET` restores them.
+    fn uidl_entries(&mut self, entries: &[UidlEntry]) -> Vec<UidlEntry> {
+        self.numbering.refresh(entries, &self.deleted);
+
+        let mut entries = self.numbering.listing();
+        entries.retain(|entry| !self.deleted.contains(&entry.id));
+        entries
+    }
+
+    /// The messages of a scan listing of the current session, numbered as in the first one
+    fn list_entries(&self, entries: Vec<ListEntry>) -> Vec<ListEntry> {
+        let originals = self.numbering.originals();
+
+        let mut entries: Vec<ListEntry> = entries
+            .into_iter()
+            .filter_map(|entry| Some(ListEntry { id: *originals.get(&entry.id)?, size: entry.size }))
+            .collect();
+
+        entries.sort_by_key(|entry| entry.id);
+        entries
+    }
+}
+
+impl<C, F> std::fmt::Debug for Resilient<C, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resilient")
+            .field("connected", &self.client.is_some())
+            .field("backoff", &self.backoff)
+            .field("deleted", &self.deleted)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Numbering {
+    fn new(entries: &[UidlEntry]) -> Self {
+        Self {
+            uids:    entries.iter().map(|entry| (entry.id, entry.uid.clone())).collect(),
+            current: entries.iter().map(|entry| (entry.uid.clone(), entry.id)).collect(),
+        }
+    }
+
+    /// Numbers of a new session
+    fn remap(&mut self, entries: &[UidlEntry]) {
+        self.current = entries.iter().map(|entry| (entry.uid.clone(), entry.id)).collect();
+    }
+
+    /// Numbers listed again in the same session, keeping those of the messages `deleted` in it, which are not listed
+    fn refresh(&mut self, entries: &[UidlEntry], deleted: &[u64]) {
+        let kept: Vec<(String, u64)> = deleted
+            .iter()
+            .filter_map(|id| {
+                let uid = self.uids.get(id)?;
+                Some((uid.clone(), *self.current.get(uid)?))
+            })
+            .collect();
+
+        self.remap(entries);
+        self.current.extend(kept);
+    }
+
+    /// Number in the current session of the message numbered `id` in the first one
+    fn current(&self, id: u64) -> Result<u64> {
+        self.uids
+            .get(&id)
+            .and_then(|uid| self.current.get(uid))
+            .copied()
+            .ok_or(Pop3Error::UnknownMessage { id })
+    }
+
+    /// Numbers in the first session, by number in the current one
+    fn originals(&self) -> HashMap<u64, u64> {
+        self.uids
+            .iter()
+            .filter_map(|(&id, uid)| Some((*self.current.get(uid)?, id)))
+            .collect()
+    }
+
+    /// Unique-id listing of the messages of the first session still in the maildrop
+    fn listing(&self) -> Vec<UidlEntry> {
+        let mut entries: Vec<UidlEntry> = self.uids
+            .iter()
+            .filter(|(_, uid)| self.current.contains_key(*uid))
+            .map(|(&id, uid)| UidlEntry { id, uid: uid.clone() })
+            .collect();
+
+        entries.sort_by_key(|entry| entry.id);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(entries: &[(u64, &str)]) -> Vec<UidlEntry> {
+        entries.iter().map(|&(id, uid)| UidlEntry { id, uid: uid.into() }).collect()
+    }
+
+    #[test]
+    fn delays() {
+        let backoff = Backoff { initial: Duration::from_secs(1), max: Duration::from_secs(10), attempts: 5 };
+
+        for (attempt, full) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10), (40, 10)] {
+            let delay = backoff.delay(attempt);
+            let full = Duration::from_secs(full);
+            assert!(delay <= full && delay >= full / 2, "attempt {attempt}: {delay:?}");
+        }
+    }
+
+    #[test]
+    fn numbering() {
+        let mut numbering = Numbering::new(&entries(&[(1, "a"), (2, "b"), (3, "c")]));
+        assert_eq!(numbering.current(2).unwrap(), 2);
+
+        // b was deleted by another session, d arrived
+        numbering.remap(&entries(&[(1, "a"), (2, "c"), (3, "d")]));
+
+        assert_eq!(numbering.current(3).unwrap(), 2);
+        assert!(matches!(numbering.current(2), Err(Pop3Error::UnknownMessage { id: 2 })));
+        assert!(matches!(numbering.current(4), Err(Pop3Error::UnknownMessage { id: 4 })));
+        assert_eq!(numbering.originals(), HashMap::from([(1, 1), (2, 3)]));
+        assert_eq!(numbering.listing(), entries(&[(1, "a"), (3, "c")]));
+    }
+
+    #[test]
+    fn refresh() {
+        let mut numbering = Numbering::new(&entries(&[(1, "a"), (2, "b"), (3, "c")]));
+
+        // a is marked as deleted, so the listing omits it
+        numbering.refresh(&entries(&[(2, "b"), (3, "c")]), &[1]);
+
+        assert_eq!(numbering.current(1).unwrap(), 1);
+        assert_eq!(numbering.listing(), entries(&[(1, "a"), (2, "b"), (3, "c")]));
+    }
+}
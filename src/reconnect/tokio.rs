@@ -0,0 +1,198 @@
+use std::future::Future;
+
+use ::tokio::io::{AsyncRead, AsyncWrite};
+use ::tokio::time;
+
+use bytes::Bytes;
+
+use super::{is_transient, Backoff, Numbering, Resilient};
+use crate::{Headers, ListEntry, Pop3Error, Response, Result, AsyncClient, UidlEntry};
+
+impl<S, F, Fut> Resilient<AsyncClient<S>, F>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<AsyncClient<S>>>,
+{
+    /// Open a session with `connect`, which returns an authorized client and is called again to reconnect
+    ///
+    /// Failed attempts are retried as after a connection loss. Errors other than transport failures and temporary refusals,
+    /// e.g. rejected credentials, are returned at once.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// use pop3_client::reconnect::{Backoff, Resilient};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// let connect = || async {
+    ///     let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    ///     client.login("sweet_username", "very_secret_password").await?;
+    ///     Ok::<_, Pop3Error>(client)
+    /// };
+    ///
+    /// let mut client = Resilient::connect_async(connect, Backoff::default()).await?;
+    /// let message = client.retr(1).await?;
+    /// client.quit().await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_async(mut connect: F, backoff: Backoff) -> Result<Self> {
+        let (client, entries) = open(&mut connect, &backoff, false).await?;
+        Ok(Self::new(client, connect, backoff, &entries))
+    }
+
+    /// Unique ids of the messages still in the maildrop, see [`AsyncClient::uidl_all`]
+    pub async fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        let entries = self.retry(async |client, _| client.uidl_all().await).await?;
+        Ok(self.uidl_entries(&entries))
+    }
+
+    /// Scan listing of the messages still in the maildrop, see [`AsyncClient::list_all`]
+    pub async fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        let entries = self.retry(async |client, _| client.list_all().await).await?;
+        Ok(self.list_entries(entries))
+    }
+
+    /// Scan listing of one message, see [`AsyncClient::list_one`]
+    pub async fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        let entry = self.retry(async |client, numbering| client.list_one(numbering.current(id)?).await).await?;
+        Ok(ListEntry { id, size: entry.size })
+    }
+
+    /// Full content of a message, see [`AsyncClient::retr`]
+    pub async fn retr(&mut self, id: u64) -> Result<Bytes> {
+        self.retry(async |client, numbering| client.retr(numbering.current(id)?).await).await
+    }
+
+    /// Headers and first lines of a message, see [`AsyncClient::top`]
+    pub async fn top(&mut self, id: u64, lines: u64) -> Result<Response> {
+        self.retry(async |client, numbering| client.top(numbering.current(id)?, lines).await).await
+    }
+
+    /// Parsed header section of a message, see [`AsyncClient::headers`]
+    pub async fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.retry(async |client, numbering| client.headers(numbering.current(id)?).await).await
+    }
+
+    /// Keep the connection alive, reconnecting if it was lost, see [`AsyncClient::noop`]
+    pub async fn noop(&mut self) -> Result<()> {
+        self.retry(async |client, _| client.noop().await).await
+    }
+
+    /// Mark a message as deleted, see [`AsyncClient::dele`]
+    ///
+    /// # Errors
+    /// - [`Pop3Error::DeletionsLost`] if the connection is lost, listing this message along with the others marked in the session.
+    ///   The client is reconnected, but the deletions are not sent again.
+    pub async fn dele(&mut self, id: u64) -> Result<Response> {
+        if self.client.is_none() {
+            self.reconnect().await?;
+        }
+
+        let result = match self.client.as_mut() {
+            Some(client) => match self.numbering.current(id) {
+                Ok(current) => client.dele(current).await,
+                Err(e) => Err(e),
+            },
+            None => Err(Pop3Error::ConnectionClosed),
+        };
+
+        match result {
+            Ok(response) => {
+                self.deleted.push(id);
+                Ok(response)
+            }
+            Err(e) if e.is_connection_lost() => {
+                // Reported as lost by the reconnection
+                self.deleted.push(id);
+                self.client = None;
+                self.reconnect().await?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Unmark the messages marked as deleted, see [`AsyncClient::rset`]
+    pub async fn rset(&mut self) -> Result<Response> {
+        // Losing the deletions is what is asked, so they are not reported if the connection is lost
+        self.deleted.clear();
+        self.retry(async |client, _| client.rset().await).await
+    }
+
+    /// Enter the UPDATE state, in which the server removes the messages marked as deleted, and end the session
+    ///
+    /// The command is not retried, as the server may have committed the deletions before the connection was lost.
+    pub async fn quit(self) -> Result<()> {
+        match self.client {
+            Some(client) => client.quit().await,
+            None if self.deleted.is_empty() => Ok(()),
+            None => Err(Pop3Error::DeletionsLost { ids: self.deleted }),
+        }
+    }
+
+    /// Run `command`, reconnecting and running it again as long as the connection fails
+    async fn retry<T, R>(&mut self, mut command: R) -> Result<T>
+    where
+        R: AsyncFnMut(&mut AsyncClient<S>, &Numbering) -> Result<T>,
+    {
+        let mut retries = 0;
+
+        loop {
+            if self.client.is_none() {
+                self.reconnect().await?;
+            }
+
+            let client = self.client.as_mut().ok_or(Pop3Error::ConnectionClosed)?;
+
+            match command(client, &self.numbering).await {
+                Err(e) if e.is_connection_lost() => {
+                    self.client = None;
+
+                    if retries == self.backoff.attempts {
+                        return Err(e);
+                    }
+
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        let (client, entries) = open(&mut self.connect, &self.backoff, true).await?;
+        self.reconnected(client, &entries)
+    }
+}
+
+/// Connect and list the unique-ids, retrying with `backoff`, after a first delay when `reconnecting`
+async fn open<S, F, Fut>(connect: &mut F, backoff: &Backoff, reconnecting: bool) -> Result<(AsyncClient<S>, Vec<UidlEntry>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<AsyncClient<S>>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        if reconnecting || attempt > 0 {
+            time::sleep(backoff.delay(attempt)).await;
+        }
+
+        let result = match connect().await {
+            Ok(mut client) => client.uidl_all().await.map(|entries| (entries, client)),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok((entries, client)) => return Ok((client, entries)),
+            Err(e) if is_transient(&e) && attempt + 1 < backoff.attempts => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 #[derive(Debug, Eq, PartialEq)]
 pub enum Command<'a> {
     Apop { id: &'a str, token: &'a str },
-    Auth,
+    Auth { mechanism: &'a str, initial: Option<&'a str> },
+    AuthResponse { data: &'a str },
     Noop,
     Uidl { id: Option<u64>},
     Top  { id: u64, lines: u64 },
@@ -14,6 +15,7 @@ pub enum Command<'a> {
     Pass { data: &'a str },
     Quit,
     Capa,
+    Stls,
     Greet,
 }
 
@@ -21,6 +23,7 @@ impl<'a> Command <'a> {
     pub fn is_response_multiline(&self) -> bool {
         match self {
             Self::Top   { .. } => true,
+            Self::Capa         => true,
             Self::Retr  { .. } => true,
             Self::List  { id } => id.is_none(),
             Self::Uidl  { id } => id.is_none(),
@@ -30,11 +33,48 @@ impl<'a> Command <'a> {
         }
     }
 
+    /// Whether the command may be sent in a pipeline: it neither changes the session state nor takes part in an exchange
+    pub fn is_pipelinable(&self) -> bool {
+        matches!(
+            self,
+            Self::Noop | Self::Uidl { .. } | Self::Top { .. } | Self::Dele { .. } | Self::Retr { .. }
+                | Self::Rset | Self::List { .. } | Self::Stat | Self::Capa
+        )
+    }
+
+    /// The command keyword, or `greeting` for the server greeting
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Apop { .. }         => "APOP",
+            Self::Auth { .. }         => "AUTH",
+            Self::AuthResponse { .. } => "AUTH",
+            Self::Noop                => "NOOP",
+            Self::Uidl { .. }         => "UIDL",
+            Self::Top  { .. }         => "TOP",
+            Self::Dele { .. }         => "DELE",
+            Self::Retr { .. }         => "RETR",
+            Self::Rset                => "RSET",
+            Self::List { .. }         => "LIST",
+            Self::Stat                => "STAT",
+            Self::User { .. }         => "USER",
+            Self::Pass { .. }         => "PASS",
+            Self::Quit                => "QUIT",
+            Self::Capa                => "CAPA",
+            Self::Stls                => "STLS",
+            Self::Greet               => "greeting",
+        }
+    }
+
     pub fn to_request(&self) -> String {
         match self {
             Self::Apop { id, token } => format!("APOP {id} {token}\r\n"),
-            Self::Auth               => "".into(),
-            Self::Capa               => "CAPA".into(),
+            Self::Auth { mechanism, initial } => match initial {
+                Some(v) => format!("AUTH {mechanism} {v}\r\n"),
+                None    => format!("AUTH {mechanism}\r\n"),
+            },
+            Self::AuthResponse { data } => format!("{data}\r\n"),
+            Self::Capa               => "CAPA\r\n".into(),
+            Self::Stls               => "STLS\r\n".into(),
             Self::Greet => "".into(),
             Self::User { data }      => format!("USER {data}\r\n"),
             Self::Pass { data }      => format!("PASS {data}\r\n"),
@@ -0,0 +1,673 @@
+//! SASL mechanisms for the `AUTH` command ([RFC 5034])
+//!
+//! [RFC 5034]: https://tools.ietf.org/html/rfc5034
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{Pop3Error, Result};
+
+/// TLS channel binding data ([RFC 5056]) of the current connection
+///
+/// [RFC 5056]: https://tools.ietf.org/html/rfc5056
+#[derive(Debug, Clone)]
+pub struct ChannelBinding {
+    /// Channel binding type, e.g. `tls-exporter` ([RFC 9266])
+    ///
+    /// [RFC 9266]: https://tools.ietf.org/html/rfc9266
+    pub kind: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// A SASL authentication mechanism driven by the `AUTH` exchange
+///
+/// The client takes care of the base64 encoding and of the protocol framing, so the mechanism only deals with raw challenges and responses.
+/// An error returned by the mechanism cancels the exchange with `*`.
+pub trait SaslMechanism {
+    /// Called before the exchange starts with the channel binding data, if the connection is protected by TLS
+    fn set_channel_binding(&mut self, _binding: Option<ChannelBinding>) {}
+
+    /// Mechanism name as registered with IANA, e.g. `PLAIN`
+    fn name(&self) -> &str;
+
+    /// Response sent together with the `AUTH` command, if the mechanism is client-first
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Answer the (decoded) server challenge
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>>;
+
+    /// Called once the server accepted the authentication, before the session is considered authorized
+    fn complete(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the server rejected the authentication, allowing the mechanism to give a more specific error
+    fn failure(&mut self, error: Pop3Error) -> Pop3Error {
+        error
+    }
+}
+
+/// The `PLAIN` mechanism ([RFC 4616]), which sends the credentials in a single initial response
+///
+/// [RFC 4616]: https://tools.ietf.org/html/rfc4616
+pub struct Plain<'a> {
+    authzid:  Option<&'a str>,
+    username: &'a str,
+    password: &'a str,
+}
+
+impl<'a> Plain<'a> {
+    pub fn new(username: &'a str, password: &'a str) -> Self {
+        Self { authzid: None, username, password }
+    }
+
+    /// Act on behalf of another identity, if the server allows it
+    pub fn authzid(mut self, authzid: &'a str) -> Self {
+        self.authzid = Some(authzid);
+        self
+    }
+
+    fn message(&self) -> Vec<u8> {
+        [self.authzid.unwrap_or(""), self.username, self.password].join("\0").into_bytes()
+    }
+}
+
+impl SaslMechanism for Plain<'_> {
+    fn name(&self) -> &str {
+        "PLAIN"
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        Some(self.message())
+    }
+
+    fn respond(&mut self, _challenge: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.message())
+    }
+}
+
+/// The obsolete but widespread `LOGIN` mechanism, which answers the `Username:` and `Password:` prompts
+pub struct Login<'a> {
+    username: &'a str,
+    password: &'a str,
+    step:     usize,
+}
+
+impl<'a> Login<'a> {
+    pub fn new(username: &'a str, password: &'a str) -> Self {
+        Self { username, password, step: 0 }
+    }
+}
+
+impl SaslMechanism for Login<'_> {
+    fn name(&self) -> &str {
+        "LOGIN"
+    }
+
+    fn respond(&mut self, _challenge: &[u8]) -> Result<Vec<u8>> {
+        self.step += 1;
+
+        match self.step {
+            1 => Ok(self.username.as_bytes().to_vec()),
+            2 => Ok(self.password.as_bytes().to_vec()),
+            _ => Err(Pop3Error::Sasl("unexpected LOGIN challenge".into())),
+        }
+    }
+}
+
+/// The `XOAUTH2` mechanism used by Google and Microsoft, which sends an OAuth 2.0 bearer token
+///
+/// See the [Google documentation] for the protocol details.
+///
+/// [Google documentation]: https://developers.google.com/gmail/imap/xoauth2-protocol
+pub struct XOAuth2<'a> {
+    username: &'a str,
+    token:    &'a str,
+    error:    Option<Pop3Error>,
+}
+
+impl<'a> XOAuth2<'a> {
+    pub fn new(username: &'a str, token: &'a str) -> Self {
+        Self { username, token, error: None }
+    }
+}
+
+impl SaslMechanism for XOAuth2<'_> {
+    fn name(&self) -> &str {
+        "XOAUTH2"
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        Some(format!("user={}\x01auth=Bearer {}\x01\x01", self.username, self.token).into_bytes())
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        // The only challenge is the error report, acknowledged with an empty response
+        self.error = Some(oauth_error(challenge)?);
+        Ok(vec![])
+    }
+
+    fn failure(&mut self, error: Pop3Error) -> Pop3Error {
+        self.error.take().unwrap_or(error)
+    }
+}
+
+/// The `OAUTHBEARER` mechanism ([RFC 7628]), which sends an OAuth 2.0 bearer token
+///
+/// [RFC 7628]: https://tools.ietf.org/html/rfc7628
+pub struct OAuthBearer<'a> {
+    username: &'a str,
+    token:    &'a str,
+    host:     Option<&'a str>,
+    port:     Option<u16>,
+    error:    Option<Pop3Error>,
+}
+
+impl<'a> OAuthBearer<'a> {
+    pub fn new(username: &'a str, token: &'a str) -> Self {
+        Self { username, token, host: None, port: None, error: None }
+    }
+
+    /// Host name of the server, sent along with the token
+    pub fn host(mut self, host: &'a str) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    /// Port of the server, sent along with the token
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+}
+
+impl SaslMechanism for OAuthBearer<'_> {
+    fn name(&self) -> &str {
+        "OAUTHBEARER"
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        let mut message = format!("n,a={},\x01", saslname(self.username));
+
+        if let Some(host) = self.host {
+            message.push_str(&format!("host={host}\x01"));
+        }
+
+        if let Some(port) = self.port {
+            message.push_str(&format!("port={port}\x01"));
+        }
+
+        message.push_str(&format!("auth=Bearer {}\x01\x01", self.token));
+
+        Some(message.into_bytes())
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        // The only challenge is the error report, acknowledged with a single %x01
+        self.error = Some(oauth_error(challenge)?);
+        Ok(vec![0x01])
+    }
+
+    fn failure(&mut self, error: Pop3Error) -> Pop3Error {
+        self.error.take().unwrap_or(error)
+    }
+}
+
+/// The `CRAM-MD5` mechanism ([RFC 2195]), which proves the knowledge of the password without sending it
+///
+/// [RFC 2195]: https://tools.ietf.org/html/rfc2195
+pub struct CramMd5<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+impl<'a> CramMd5<'a> {
+    pub fn new(username: &'a str, password: &'a str) -> Self {
+        Self { username, password }
+    }
+}
+
+impl SaslMechanism for CramMd5<'_> {
+    fn name(&self) -> &str {
+        "CRAM-MD5"
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        let mut mac = Hmac::<Md5>::new_from_slice(self.password.as_bytes())
+            .map_err(|e| Pop3Error::Sasl(e.to_string()))?;
+
+        mac.update(challenge);
+
+        Ok(format!("{} {}", self.username, hex(&mac.finalize().into_bytes())).into_bytes())
+    }
+}
+
+/// Hash function of a SCRAM mechanism
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ScramHash {
+    Sha1,
+    Sha256,
+}
+
+impl ScramHash {
+    fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1   => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        // HMAC accepts keys of any length
+        match self {
+            Self::Sha1   => Hmac::<Sha1>::new_from_slice(key).map(|m| m.chain_update(data).finalize().into_bytes().to_vec()),
+            Self::Sha256 => Hmac::<Sha256>::new_from_slice(key).map(|m| m.chain_update(data).finalize().into_bytes().to_vec()),
+        }
+        .unwrap_or_default()
+    }
+
+    fn salted_password(self, password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        match self {
+            Self::Sha1 => {
+                let mut out = [0u8; 20];
+                pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, &mut out);
+                out.to_vec()
+            }
+            Self::Sha256 => {
+                let mut out = [0u8; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut out);
+                out.to_vec()
+            }
+        }
+    }
+}
+
+enum ScramState {
+    Initial,
+    ClientFirst { bare: String, gs2_header: String },
+    ClientFinal { server_signature: Vec<u8> },
+    Verified,
+}
+
+/// The `SCRAM-SHA-1` and `SCRAM-SHA-256` mechanisms ([RFC 5802], [RFC 7677]), with optional channel binding (`-PLUS` variants)
+///
+/// The server is authenticated as well: the exchange fails unless the server proves the knowledge of the password in its final message.
+/// The username and password are not normalized with SASLprep, so they should be plain ASCII.
+///
+/// [RFC 5802]: https://tools.ietf.org/html/rfc5802
+/// [RFC 7677]: https://tools.ietf.org/html/rfc7677
+pub struct Scram<'a> {
+    hash:     ScramHash,
+    name:     String,
+    username: &'a str,
+    password: &'a str,
+    nonce:    String,
+    plus:     bool,
+    binding:  Option<ChannelBinding>,
+    state:    ScramState,
+}
+
+impl<'a> Scram<'a> {
+    pub fn new(hash: ScramHash, username: &'a str, password: &'a str) -> Self {
+        let mut nonce = [0u8; 24];
+        // Without a random nonce the exchange would be replayable, there is no sane fallback
+        getrandom::getrandom(&mut nonce).expect("no random source available");
+
+        Self::with_nonce(hash, username, password, BASE64.encode(nonce))
+    }
+
+    pub(crate) fn with_nonce(hash: ScramHash, username: &'a str, password: &'a str, nonce: String) -> Self {
+        let name = match hash {
+            ScramHash::Sha1   => "SCRAM-SHA-1",
+            ScramHash::Sha256 => "SCRAM-SHA-256",
+        };
+
+        Self {
+            hash,
+            name: name.into(),
+            username,
+            password,
+            nonce,
+            plus: false,
+            binding: None,
+            state: ScramState::Initial,
+        }
+    }
+
+    /// `SCRAM-SHA-1` mechanism
+    pub fn sha1(username: &'a str, password: &'a str) -> Self {
+        Self::new(ScramHash::Sha1, username, password)
+    }
+
+    /// `SCRAM-SHA-256` mechanism
+    pub fn sha256(username: &'a str, password: &'a str) -> Self {
+        Self::new(ScramHash::Sha256, username, password)
+    }
+
+    /// Bind the authentication to the TLS connection, using the `-PLUS` variant of the mechanism
+    ///
+    /// The exchange fails if the connection is not protected by TLS.
+    pub fn channel_binding(mut self) -> Self {
+        if !self.plus {
+            self.plus = true;
+            self.name.push_str("-PLUS");
+        }
+        self
+    }
+
+    fn client_first(&mut self) -> Result<Vec<u8>> {
+        let gs2_header = if self.plus {
+            let binding = self.binding
+                .as_ref()
+                .ok_or_else(|| Pop3Error::Sasl("channel binding requires a TLS connection".into()))?;
+            format!("p={},,", binding.kind)
+        } else {
+            "n,,".into()
+        };
+
+        let bare = format!("n={},r={}", saslname(self.username), self.nonce);
+        let message = format!("{gs2_header}{bare}");
+
+        self.state = ScramState::ClientFirst { bare, gs2_header };
+
+        Ok(message.into_bytes())
+    }
+
+    fn client_final(&mut self, server_first: &[u8], bare: &str, gs2_header: &str) -> Result<Vec<u8>> {
+        let server_first = std::str::from_utf8(server_first)
+            .map_err(Pop3Error::InvalidString)?;
+
+        let attribute = |name: char| server_first
+            .split(',')
+            .find_map(|a| a.strip_prefix(name).and_then(|a| a.strip_prefix('=')))
+            .ok_or_else(|| Pop3Error::Sasl(format!("missing '{name}' in SCRAM server-first-message")));
+
+        if let Ok(error) = attribute('e') {
+            return Err(Pop3Error::Sasl(format!("SCRAM server error: {error}")));
+        }
+
+        let nonce = attribute('r')?;
+        let salt = BASE64.decode(attribute('s')?)
+            .map_err(|_| Pop3Error::Sasl("invalid salt in SCRAM server-first-message".into()))?;
+        let iterations = attribute('i')?
+            .parse::<u32>()
+            .map_err(Pop3Error::InvalidNumber)?;
+
+        if !nonce.starts_with(&self.nonce) || nonce.len() == self.nonce.len() {
+            return Err(Pop3Error::Sasl("SCRAM server nonce does not extend the client one".into()));
+        }
+
+        let mut binding = gs2_header.as_bytes().to_vec();
+        if self.plus {
+            if let Some(b) = &self.binding {
+                binding.extend_from_slice(&b.data);
+            }
+        }
+
+        let without_proof = format!("c={},r={nonce}", BASE64.encode(&binding));
+        let auth_message = format!("{bare},{server_first},{without_proof}");
+
+        let salted_password = self.hash.salted_password(self.password.as_bytes(), &salt, iterations);
+        let client_key = self.hash.hmac(&salted_password, b"Client Key");
+        let stored_key = self.hash.hash(&client_key);
+        let client_signature = self.hash.hmac(&stored_key, auth_message.as_bytes());
+        let server_key = self.hash.hmac(&salted_password, b"Server Key");
+
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(k, s)| k ^ s)
+            .collect();
+
+        self.state = ScramState::ClientFinal {
+            server_signature: self.hash.hmac(&server_key, auth_message.as_bytes()),
+        };
+
+        Ok(format!("{without_proof},p={}", BASE64.encode(proof)).into_bytes())
+    }
+}
+
+impl SaslMechanism for Scram<'_> {
+    fn set_channel_binding(&mut self, binding: Option<ChannelBinding>) {
+        self.binding = binding;
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        // A failure is reported on the first challenge instead, as the initial response can not fail
+        self.client_first().ok()
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        match std::mem::replace(&mut self.state, ScramState::Initial) {
+            ScramState::Initial => self.client_first(),
+            ScramState::ClientFirst { bare, gs2_header } => self.client_final(challenge, &bare, &gs2_header),
+            ScramState::ClientFinal { server_signature } => {
+                let verifier = std::str::from_utf8(challenge)
+                    .ok()
+                    .and_then(|c| c.strip_prefix("v="))
+                    .and_then(|v| BASE64.decode(v.split(',').next().unwrap_or(v)).ok());
+
+                match verifier {
+                    Some(v) if v == server_signature => {
+                        self.state = ScramState::Verified;
+                        Ok(vec![])
+                    }
+                    _ => Err(Pop3Error::Sasl("SCRAM server signature verification failed".into())),
+                }
+            }
+            ScramState::Verified => Err(Pop3Error::Sasl("unexpected SCRAM challenge".into())),
+        }
+    }
+
+    fn complete(&mut self) -> Result<()> {
+        match self.state {
+            ScramState::Verified => Ok(()),
+            _ => Err(Pop3Error::Sasl("server did not send the SCRAM server signature".into())),
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Parse the JSON error report sent by the server as an OAuth challenge
+fn oauth_error(challenge: &[u8]) -> Result<Pop3Error> {
+    let report: serde_json::Value = serde_json::from_slice(challenge)
+        .map_err(|_| Pop3Error::Sasl("invalid OAuth error report".into()))?;
+
+    let field = |name: &str| report.get(name)
+        .and_then(|v| v.as_str())
+        .map(String::from);
+
+    Ok(Pop3Error::OAuth {
+        status:               field("status").unwrap_or_default(),
+        scope:                field("scope"),
+        schemes:              field("schemes"),
+        openid_configuration: field("openid-configuration"),
+    })
+}
+
+/// Escape a GS2 `saslname` ([RFC 5801])
+///
+/// [RFC 5801]: https://tools.ietf.org/html/rfc5801
+fn saslname(name: &str) -> String {
+    name.replace('=', "=3D").replace(',', "=2C")
+}
+
+/// Longest `AUTH` command line allowed to carry an initial response, CRLF included
+const MAX_AUTH_LINE: usize = 255;
+
+pub(crate) fn encode(data: &[u8]) -> String {
+    BASE64.encode(data)
+}
+
+pub(crate) fn encode_initial(data: &[u8]) -> String {
+    if data.is_empty() {
+        // An empty initial response is sent as a single `=`, to tell it from an absent one
+        "=".into()
+    } else {
+        encode(data)
+    }
+}
+
+pub(crate) fn decode(challenge: &[u8]) -> Result<Vec<u8>> {
+    let challenge = challenge.trim_ascii();
+
+    BASE64.decode(challenge)
+        .map_err(|_| Pop3Error::Sasl("invalid base64 in server challenge".into()))
+}
+
+/// Whether the encoded initial response fits into the `AUTH` command line
+pub(crate) fn fits_auth_line(mechanism: &str, initial: &str) -> bool {
+    "AUTH \r\n".len() + mechanism.len() + 1 + initial.len() <= MAX_AUTH_LINE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain() {
+        let mut plain = Plain::new("tim", "tanstaaftanstaaf");
+        assert_eq!(plain.initial_response().unwrap(), b"\0tim\0tanstaaftanstaaf");
+
+        let mut plain = Plain::new("tim", "secret").authzid("admin");
+        assert_eq!(plain.initial_response().unwrap(), b"admin\0tim\0secret");
+    }
+
+    #[test]
+    fn login() {
+        let mut login = Login::new("tim", "secret");
+        assert!(login.initial_response().is_none());
+        assert_eq!(login.respond(b"Username:").unwrap(), b"tim");
+        assert_eq!(login.respond(b"Password:").unwrap(), b"secret");
+        assert!(login.respond(b"").is_err());
+    }
+
+    #[test]
+    fn xoauth2() {
+        let mut xoauth2 = XOAuth2::new("someuser@example.com", "ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg");
+        assert_eq!(
+            xoauth2.initial_response().unwrap(),
+            b"user=someuser@example.com\x01auth=Bearer ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg\x01\x01"
+        );
+
+        let challenge = br#"{"status":"401","schemes":"bearer","scope":"https://mail.google.com/"}"#;
+        assert_eq!(xoauth2.respond(challenge).unwrap(), b"");
+
+        match xoauth2.failure(Pop3Error::server("denied")) {
+            Pop3Error::OAuth { status, scope, schemes, openid_configuration } => {
+                assert_eq!(status, "401");
+                assert_eq!(scope.as_deref(), Some("https://mail.google.com/"));
+                assert_eq!(schemes.as_deref(), Some("bearer"));
+                assert_eq!(openid_configuration, None);
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[test]
+    fn oauthbearer() {
+        let mut bearer = OAuthBearer::new("user@example.com", "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==")
+            .host("server.example.com")
+            .port(143);
+
+        assert_eq!(
+            bearer.initial_response().unwrap(),
+            b"n,a=user@example.com,\x01host=server.example.com\x01port=143\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"
+        );
+
+        let challenge = br#"{"status":"invalid_token","scope":"example_scope","openid-configuration":"https://example.com/.well-known/openid-configuration"}"#;
+        assert_eq!(bearer.respond(challenge).unwrap(), b"\x01");
+        assert!(matches!(bearer.failure(Pop3Error::server("denied")), Pop3Error::OAuth { status, .. } if status == "invalid_token"));
+
+        assert!(bearer.respond(b"not json").is_err());
+        assert_eq!(saslname("a=b,c"), "a=3Db=2Cc");
+    }
+
+    #[test]
+    fn cram_md5() {
+        let mut cram = CramMd5::new("tim", "tanstaaftanstaaf");
+        assert!(cram.initial_response().is_none());
+        assert_eq!(
+            cram.respond(b"<1896.697170952@postoffice.reston.mci.net>").unwrap(),
+            b"tim b913a602c7eda7a495b4e6e7334d3890"
+        );
+    }
+
+    #[test]
+    fn scram_sha1() {
+        let mut scram = Scram::with_nonce(ScramHash::Sha1, "user", "pencil", "fyko+d2lbbFgONRv9qkxdawL".into());
+        assert_eq!(scram.name(), "SCRAM-SHA-1");
+        assert_eq!(scram.initial_response().unwrap(), b"n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL");
+        assert_eq!(
+            scram.respond(b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096").unwrap(),
+            b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+        );
+        assert!(scram.complete().is_err());
+        assert_eq!(scram.respond(b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ=").unwrap(), b"");
+        assert!(scram.complete().is_ok());
+    }
+
+    #[test]
+    fn scram_sha256() {
+        let mut scram = Scram::with_nonce(ScramHash::Sha256, "user", "pencil", "rOprNGfwEbeRWgbNEkqO".into());
+        assert_eq!(scram.name(), "SCRAM-SHA-256");
+        assert_eq!(scram.initial_response().unwrap(), b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(
+            scram.respond(b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").unwrap(),
+            b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert_eq!(scram.respond(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=").unwrap(), b"");
+        assert!(scram.complete().is_ok());
+    }
+
+    #[test]
+    fn scram_bad_server() {
+        let mut scram = Scram::with_nonce(ScramHash::Sha256, "user", "pencil", "rOprNGfwEbeRWgbNEkqO".into());
+        scram.initial_response();
+        scram.respond(b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").unwrap();
+        assert!(scram.respond(b"v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=").is_err());
+
+        let mut scram = Scram::with_nonce(ScramHash::Sha256, "user", "pencil", "rOprNGfwEbeRWgbNEkqO".into());
+        scram.initial_response();
+        assert!(scram.respond(b"r=somethingelse,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").is_err());
+    }
+
+    #[test]
+    fn scram_plus() {
+        let mut scram = Scram::with_nonce(ScramHash::Sha256, "user", "pencil", "rOprNGfwEbeRWgbNEkqO".into())
+            .channel_binding();
+        assert_eq!(scram.name(), "SCRAM-SHA-256-PLUS");
+        assert!(scram.initial_response().is_none());
+
+        scram.set_channel_binding(Some(ChannelBinding { kind: "tls-exporter", data: vec![1, 2, 3] }));
+        assert_eq!(scram.initial_response().unwrap(), b"p=tls-exporter,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+
+        let client_final = scram.respond(b"r=rOprNGfwEbeRWgbNEkqOserver,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").unwrap();
+        let expected = format!("c={},", BASE64.encode(b"p=tls-exporter,,\x01\x02\x03"));
+        assert!(client_final.starts_with(expected.as_bytes()));
+    }
+
+    #[test]
+    fn codec() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode_initial(b""), "=");
+        assert_eq!(encode(b"\0tim\0secret"), "AHRpbQBzZWNyZXQ=");
+        assert_eq!(decode(b"VXNlcm5hbWU6\r\n").unwrap(), b"Username:");
+        assert!(decode(b"!!!").is_err());
+        assert!(fits_auth_line("PLAIN", &"A".repeat(242)));
+        assert!(!fits_auth_line("PLAIN", &"A".repeat(243)));
+    }
+}
@@ -0,0 +1,149 @@
+//! Sessions whose POP3 state is part of their type ([RFC 1939] section 3)
+//!
+//! A [`Client<Authorization, _>`](Client) only offers the commands allowed before login, and authorizing consumes it
+//! to return a [`Client<Transaction, _>`](Client) offering the mailbox commands. Calling `stat` before login,
+//! or `login` twice, is thus a compile error rather than a server error. Quitting a transaction enters the
+//! UPDATE state, where the server commits the deletions, and ends the session.
+//!
+//! The wrapped client is either a [`SyncClient`](crate::SyncClient) or an [`AsyncClient`](crate::AsyncClient).
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use pop3_client::session::Client;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! let client = Client::new(AsyncClient::connect("pop3.mailtrap.io", 1100).await?)?;
+//! let mut client = client.login("sweet_username", "very_secret_password").await?;
+//!
+//! let (messages, octets) = client.stat().await?;
+//! client.quit().await?;
+//! #    Ok(())
+//! # }
+//! ```
+//!
+//! Mailbox commands are not available before login:
+//!
+//! ```compile_fail
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use pop3_client::session::Client;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! let mut client = Client::new(AsyncClient::connect("pop3.mailtrap.io", 1100).await?)?;
+//! client.stat().await?; // Shouldn't compile, as the session is not authorized
+//! #    Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 1939]: https://tools.ietf.org/html/rfc1939#section-3
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::{Pop3Error, Result};
+
+#[cfg(feature = "runtime-sync")]
+mod sync;
+
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
+
+/// The AUTHORIZATION state: the client identifies itself to the server
+#[derive(Debug)]
+pub enum Authorization {}
+
+/// The TRANSACTION state: the client works with the maildrop
+#[derive(Debug)]
+pub enum Transaction {}
+
+/// A client in the POP3 state `State`, wrapping a [`SyncClient`](crate::SyncClient) or an [`AsyncClient`](crate::AsyncClient)
+pub struct Client<State, C> {
+    inner: C,
+    state: PhantomData<State>,
+}
+
+/// Clients a typed session can wrap
+pub trait Inner: sealed::Sealed {
+    #[doc(hidden)]
+    fn is_authorized(&self) -> bool;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl<C: Inner> Client<Authorization, C> {
+    /// Start a typed session over a client that is not authorized yet
+    ///
+    /// # Errors
+    /// - [`Pop3Error::AlreadyAuthenticated`] if the client has already left the AUTHORIZATION state
+    pub fn new(client: C) -> Result<Self> {
+        if client.is_authorized() {
+            return Err(Pop3Error::AlreadyAuthenticated);
+        }
+
+        Ok(Self::wrap(client))
+    }
+}
+
+impl<State, C> Client<State, C> {
+    fn wrap(inner: C) -> Self {
+        Self { inner, state: PhantomData }
+    }
+
+    /// The wrapped client, for commands the typed session does not offer
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<State, C> fmt::Debug for Client<State, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("state", &std::any::type_name::<State>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A failed authorization, giving the client back to try again, e.g. with another mechanism
+pub struct Rejected<C> {
+    pub error:  Pop3Error,
+    pub client: Box<Client<Authorization, C>>,
+}
+
+impl<C> Rejected<C> {
+    fn new(error: Pop3Error, inner: C) -> Self {
+        Self { error, client: Box::new(Client::wrap(inner)) }
+    }
+}
+
+impl<C> fmt::Debug for Rejected<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rejected")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> fmt::Display for Rejected<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<C> std::error::Error for Rejected<C> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<C> From<Rejected<C>> for Pop3Error {
+    fn from(rejected: Rejected<C>) -> Self {
+        rejected.error
+    }
+}
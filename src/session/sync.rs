@@ -0,0 +1,170 @@
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+
+use super::{sealed, Authorization, Client, Inner, Rejected, Transaction};
+use crate::{Capabilities, Command, Headers, ListEntry, Response, Result, SaslMechanism, SyncClient, UidlEntry};
+
+impl<S> sealed::Sealed for SyncClient<S> {}
+
+impl<S: Read + Write> Inner for SyncClient<S> {
+    fn is_authorized(&self) -> bool {
+        SyncClient::is_authorized(self)
+    }
+}
+
+type Authorized<S> = std::result::Result<Client<Transaction, SyncClient<S>>, Rejected<SyncClient<S>>>;
+
+impl<S: Read + Write> Client<Authorization, SyncClient<S>> {
+    /// The server greeting, see [`SyncClient::greeting`]
+    pub fn greeting(&self) -> &str {
+        self.inner.greeting()
+    }
+
+    /// Get the server capabilities, see [`SyncClient::capa`]
+    pub fn capa(&mut self) -> Result<Capabilities> {
+        self.inner.capa()
+    }
+
+    /// Authorize with `USER` and `PASS`, see [`SyncClient::login`]
+    pub fn login(mut self, username: &str, password: &str) -> Authorized<S> {
+        match self.inner.login(username, password) {
+            Ok(()) => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with `APOP`, see [`SyncClient::apop`]
+    pub fn apop(mut self, id: &str, token: &str) -> Authorized<S> {
+        match self.inner.apop(id, token) {
+            Ok(_)  => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with `APOP`, computing the digest from the password, see [`SyncClient::apop_with_password`]
+    pub fn apop_with_password(mut self, id: &str, password: &str) -> Authorized<S> {
+        match self.inner.apop_with_password(id, password) {
+            Ok(_)  => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with a SASL mechanism, see [`SyncClient::authenticate`]
+    pub fn authenticate<M: SaslMechanism>(mut self, mechanism: M) -> Authorized<S> {
+        match self.inner.authenticate(mechanism) {
+            Ok(()) => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// End the session without entering the UPDATE state
+    pub fn quit(self) -> Result<()> {
+        self.inner.quit()
+    }
+}
+
+impl Client<Authorization, SyncClient> {
+    /// Upgrade the connection to TLS, see [`SyncClient::stls`]
+    #[cfg(feature = "with-rustls")]
+    pub fn stls(&mut self) -> Result<()> {
+        self.inner.stls()
+    }
+}
+
+impl<S: Read + Write> Client<Transaction, SyncClient<S>> {
+    /// The server greeting, see [`SyncClient::greeting`]
+    pub fn greeting(&self) -> &str {
+        self.inner.greeting()
+    }
+
+    /// Get the server capabilities, see [`SyncClient::capa`]
+    pub fn capa(&mut self) -> Result<Capabilities> {
+        self.inner.capa()
+    }
+
+    /// Number of messages and their total size, see [`SyncClient::stat`]
+    pub fn stat(&mut self) -> Result<(u64, u64)> {
+        self.inner.stat()
+    }
+
+    /// Raw scan listing, see [`SyncClient::list`]
+    pub fn list(&mut self, id: Option<u64>) -> Result<Response> {
+        self.inner.list(id)
+    }
+
+    /// Scan listing of all messages, see [`SyncClient::list_all`]
+    pub fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        self.inner.list_all()
+    }
+
+    /// Scan listing of one message, see [`SyncClient::list_one`]
+    pub fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        self.inner.list_one(id)
+    }
+
+    /// Full content of a message, see [`SyncClient::retr`]
+    pub fn retr(&mut self, id: u64) -> Result<Bytes> {
+        self.inner.retr(id)
+    }
+
+    /// Full content of a message with a custom size limit, see [`SyncClient::retr_with_limit`]
+    pub fn retr_with_limit(&mut self, id: u64, max_size: usize) -> Result<Bytes> {
+        self.inner.retr_with_limit(id, max_size)
+    }
+
+    /// Write the content of a message as it is received, see [`SyncClient::retr_to_writer`]
+    pub fn retr_to_writer<W: Write>(&mut self, id: u64, writer: &mut W) -> Result<u64> {
+        self.inner.retr_to_writer(id, writer)
+    }
+
+    /// Mark a message as deleted, see [`SyncClient::dele`]
+    pub fn dele(&mut self, id: u64) -> Result<Response> {
+        self.inner.dele(id)
+    }
+
+    /// Headers and first lines of a message, see [`SyncClient::top`]
+    pub fn top(&mut self, id: u64, lines: u64) -> Result<Response> {
+        self.inner.top(id, lines)
+    }
+
+    /// Parsed header section of a message, see [`SyncClient::headers`]
+    pub fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.inner.headers(id)
+    }
+
+    /// Raw unique-id listing, see [`SyncClient::uidl`]
+    pub fn uidl(&mut self, id: Option<u64>) -> Result<Response> {
+        self.inner.uidl(id)
+    }
+
+    /// Unique ids of all messages, see [`SyncClient::uidl_all`]
+    pub fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        self.inner.uidl_all()
+    }
+
+    /// Unique id of one message, see [`SyncClient::uidl_one`]
+    pub fn uidl_one(&mut self, id: u64) -> Result<UidlEntry> {
+        self.inner.uidl_one(id)
+    }
+
+    /// Unmark the messages marked as deleted, see [`SyncClient::rset`]
+    pub fn rset(&mut self) -> Result<Response> {
+        self.inner.rset()
+    }
+
+    /// Send a batch of commands and read their replies in order, see [`SyncClient::pipeline`]
+    pub fn pipeline(&mut self, commands: &[Command<'_>]) -> Vec<Result<Response>> {
+        self.inner.pipeline(commands)
+    }
+
+    /// Keep the connection alive, see [`SyncClient::noop`]
+    pub fn noop(&mut self) -> Result<()> {
+        self.inner.noop()
+    }
+
+    /// Enter the UPDATE state, in which the server removes the messages marked as deleted, and end the session
+    pub fn quit(self) -> Result<()> {
+        self.inner.quit()
+    }
+}
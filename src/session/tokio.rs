@@ -0,0 +1,175 @@
+use ::tokio::io::{AsyncRead, AsyncWrite};
+
+use bytes::Bytes;
+
+use super::{sealed, Authorization, Client, Inner, Rejected, Transaction};
+use crate::{AsyncClient, Capabilities, Command, Headers, ListEntry, Response, Result, RetrStream, SaslMechanism, UidlEntry};
+
+impl<S> sealed::Sealed for AsyncClient<S> {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Inner for AsyncClient<S> {
+    fn is_authorized(&self) -> bool {
+        AsyncClient::is_authorized(self)
+    }
+}
+
+type Authorized<S> = std::result::Result<Client<Transaction, AsyncClient<S>>, Rejected<AsyncClient<S>>>;
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<Authorization, AsyncClient<S>> {
+    /// The server greeting, see [`AsyncClient::greeting`]
+    pub fn greeting(&self) -> &str {
+        self.inner.greeting()
+    }
+
+    /// Get the server capabilities, see [`AsyncClient::capa`]
+    pub async fn capa(&mut self) -> Result<Capabilities> {
+        self.inner.capa().await
+    }
+
+    /// Authorize with `USER` and `PASS`, see [`AsyncClient::login`]
+    pub async fn login(mut self, username: &str, password: &str) -> Authorized<S> {
+        match self.inner.login(username, password).await {
+            Ok(()) => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with `APOP`, see [`AsyncClient::apop`]
+    pub async fn apop(mut self, id: &str, token: &str) -> Authorized<S> {
+        match self.inner.apop(id, token).await {
+            Ok(_)  => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with `APOP`, computing the digest from the password, see [`AsyncClient::apop_with_password`]
+    pub async fn apop_with_password(mut self, id: &str, password: &str) -> Authorized<S> {
+        match self.inner.apop_with_password(id, password).await {
+            Ok(_)  => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// Authorize with a SASL mechanism, see [`AsyncClient::authenticate`]
+    pub async fn authenticate<M: SaslMechanism>(mut self, mechanism: M) -> Authorized<S> {
+        match self.inner.authenticate(mechanism).await {
+            Ok(()) => Ok(Client::wrap(self.inner)),
+            Err(e) => Err(Rejected::new(e, self.inner)),
+        }
+    }
+
+    /// End the session without entering the UPDATE state
+    pub async fn quit(self) -> Result<()> {
+        self.inner.quit().await
+    }
+}
+
+impl Client<Authorization, AsyncClient> {
+    /// Upgrade the connection to TLS, see [`AsyncClient::stls`]
+    #[cfg(feature = "with-rustls")]
+    pub async fn stls(&mut self) -> Result<()> {
+        self.inner.stls().await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<Transaction, AsyncClient<S>> {
+    /// The server greeting, see [`AsyncClient::greeting`]
+    pub fn greeting(&self) -> &str {
+        self.inner.greeting()
+    }
+
+    /// Get the server capabilities, see [`AsyncClient::capa`]
+    pub async fn capa(&mut self) -> Result<Capabilities> {
+        self.inner.capa().await
+    }
+
+    /// Number of messages and their total size, see [`AsyncClient::stat`]
+    pub async fn stat(&mut self) -> Result<(u64, u64)> {
+        self.inner.stat().await
+    }
+
+    /// Raw scan listing, see [`AsyncClient::list`]
+    pub async fn list(&mut self, id: Option<u64>) -> Result<Response> {
+        self.inner.list(id).await
+    }
+
+    /// Scan listing of all messages, see [`AsyncClient::list_all`]
+    pub async fn list_all(&mut self) -> Result<Vec<ListEntry>> {
+        self.inner.list_all().await
+    }
+
+    /// Scan listing of one message, see [`AsyncClient::list_one`]
+    pub async fn list_one(&mut self, id: u64) -> Result<ListEntry> {
+        self.inner.list_one(id).await
+    }
+
+    /// Full content of a message, see [`AsyncClient::retr`]
+    pub async fn retr(&mut self, id: u64) -> Result<Bytes> {
+        self.inner.retr(id).await
+    }
+
+    /// Full content of a message with a custom size limit, see [`AsyncClient::retr_with_limit`]
+    pub async fn retr_with_limit(&mut self, id: u64, max_size: usize) -> Result<Bytes> {
+        self.inner.retr_with_limit(id, max_size).await
+    }
+
+    /// Write the content of a message as it is received, see [`AsyncClient::retr_to_writer`]
+    pub async fn retr_to_writer<W: AsyncWrite + Unpin>(&mut self, id: u64, writer: &mut W) -> Result<u64> {
+        self.inner.retr_to_writer(id, writer).await
+    }
+
+    /// Stream the content of a message as it is received, see [`AsyncClient::retr_stream`]
+    pub async fn retr_stream(&mut self, id: u64) -> Result<RetrStream<'_, S>> {
+        self.inner.retr_stream(id).await
+    }
+
+    /// Mark a message as deleted, see [`AsyncClient::dele`]
+    pub async fn dele(&mut self, id: u64) -> Result<Response> {
+        self.inner.dele(id).await
+    }
+
+    /// Headers and first lines of a message, see [`AsyncClient::top`]
+    pub async fn top(&mut self, id: u64, lines: u64) -> Result<Response> {
+        self.inner.top(id, lines).await
+    }
+
+    /// Parsed header section of a message, see [`AsyncClient::headers`]
+    pub async fn headers(&mut self, id: u64) -> Result<Headers> {
+        self.inner.headers(id).await
+    }
+
+    /// Raw unique-id listing, see [`AsyncClient::uidl`]
+    pub async fn uidl(&mut self, id: Option<u64>) -> Result<Response> {
+        self.inner.uidl(id).await
+    }
+
+    /// Unique ids of all messages, see [`AsyncClient::uidl_all`]
+    pub async fn uidl_all(&mut self) -> Result<Vec<UidlEntry>> {
+        self.inner.uidl_all().await
+    }
+
+    /// Unique id of one message, see [`AsyncClient::uidl_one`]
+    pub async fn uidl_one(&mut self, id: u64) -> Result<UidlEntry> {
+        self.inner.uidl_one(id).await
+    }
+
+    /// Unmark the messages marked as deleted, see [`AsyncClient::rset`]
+    pub async fn rset(&mut self) -> Result<Response> {
+        self.inner.rset().await
+    }
+
+    /// Send a batch of commands and read their replies in order, see [`AsyncClient::pipeline`]
+    pub async fn pipeline(&mut self, commands: &[Command<'_>]) -> Vec<Result<Response>> {
+        self.inner.pipeline(commands).await
+    }
+
+    /// Keep the connection alive, see [`AsyncClient::noop`]
+    pub async fn noop(&mut self) -> Result<()> {
+        self.inner.noop().await
+    }
+
+    /// Enter the UPDATE state, in which the server removes the messages marked as deleted, and end the session
+    pub async fn quit(self) -> Result<()> {
+        self.inner.quit().await
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+
+use super::{Fetcher, Report, Retention, UidStore};
+use crate::{Command, Response, Result, SyncClient, UidlEntry};
+
+impl<S: Read + Write, U: UidStore> Fetcher<SyncClient<S>, U> {
+    /// Retrieve the messages not seen yet and hand them to `sink`, in the order of the listing
+    ///
+    /// The unique-id of a message is recorded once `sink` returns successfully. The run stops at the first error,
+    /// leaving the failed message and the following ones for the next run.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{Pop3Error, SyncClient};
+    /// use pop3_client::sync::{Fetcher, MemoryStore};
+    ///
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// let mut client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// client.login("sweet_username", "very_secret_password")?;
+    ///
+    /// let mut fetcher = Fetcher::new(client, MemoryStore::new(), "sweet_username");
+    /// let report = fetcher.run(|entry, message| {
+    ///     println!("{}: {} octets", entry.uid, message.len());
+    ///     Ok(())
+    /// })?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn run<F>(&mut self, mut sink: F) -> Result<Report>
+    where
+        F: FnMut(UidlEntry, Bytes) -> Result<()>,
+    {
+        let mut report = Report::default();
+        let entries = self.client.uidl_all()?;
+
+        for entry in self.plan(entries, &mut report)? {
+            let message = self.client.retr(entry.id)?;
+            sink(entry.clone(), message)?;
+            self.delivered(entry, &mut report)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Mark for deletion the delivered messages matching `policy`, returning their entries each with the reply to its `DELE`
+    ///
+    /// The server only deletes them once the session ends with [`quit`](Self::quit). The commands are pipelined if the server supports it.
+    /// A message the server refuses to mark does not affect the others, see [`pipeline`](crate::SyncClient::pipeline).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{Pop3Error, SyncClient};
+    /// use pop3_client::sync::{Fetcher, MemoryStore, Retention};
+    ///
+    /// # fn main() -> Result<(), Pop3Error> {
+    /// # let client = SyncClient::connect("pop3.mailtrap.io", 1100)?;
+    /// # let mut fetcher = Fetcher::new(client, MemoryStore::new(), "sweet_username");
+    /// let mut policy = Retention::default().larger_than(10 * 1024 * 1024);
+    /// for (entry, reply) in fetcher.apply(&mut policy)? {
+    ///     if let Err(e) = reply {
+    ///         eprintln!("{} is kept: {e}", entry.uid);
+    ///     }
+    /// }
+    /// fetcher.quit()?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn apply(&mut self, policy: &mut Retention) -> Result<Vec<(UidlEntry, Result<Response>)>> {
+        let entries = self.client.uidl_all()?;
+        let sizes: HashMap<u64, u64> = if policy.needs_sizes() {
+            self.client.list_all()?.into_iter().map(|entry| (entry.id, entry.size)).collect()
+        } else {
+            HashMap::new()
+        };
+
+        let expired = self.expired(entries, &sizes, policy)?;
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let commands: Vec<_> = expired.iter().map(|entry| Command::Dele { id: entry.id }).collect();
+        let replies = self.client.pipeline(&commands);
+
+        Ok(expired.into_iter().zip(replies).collect())
+    }
+
+    /// End the session, committing the deletions, and return the store
+    pub fn quit(self) -> Result<U> {
+        self.client.quit()?;
+        Ok(self.store)
+    }
+}
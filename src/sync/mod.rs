@@ -0,0 +1,410 @@
+//! Incremental retrieval of the messages left on the server, keyed by their unique-id
+//!
+//! A [`Fetcher`] lists the unique-ids of the mailbox with `UIDL`, retrieves only the messages it has not seen yet,
+//! and hands each of them to a sink before recording its unique-id in a [`UidStore`]. Messages are left on the server,
+//! as with the "keep" option of fetchmail. A message whose sink fails is retrieved again by the next run,
+//! so each message is delivered at least once.
+//!
+//! A [`Retention`] policy then deletes the delivered messages matching its rules. The deletions are only committed by
+//! the server when the fetcher quits cleanly, entering the UPDATE state: if the connection is lost before, the messages stay.
+//! As the unique-id of a message is durably recorded before it is considered for deletion, a message is never deleted
+//! before being delivered, even across crashes.
+//!
+//! The unique-ids are kept per account, so a single store can serve several mailboxes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::result::Result;
+//! # use pop3_client::{AsyncClient, Pop3Error};
+//! use std::time::Duration;
+//! use pop3_client::sync::{FileStore, Fetcher, Retention};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Pop3Error> {
+//! let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+//! client.login("sweet_username", "very_secret_password").await?;
+//!
+//! let mut fetcher = Fetcher::new(client, FileStore::open("/var/lib/fetch/uids")?, "sweet_username@pop3.mailtrap.io");
+//! let report = fetcher.run(|entry, message| async move {
+//!     println!("{}: {} octets", entry.uid, message.len());
+//!     Ok(())
+//! }).await?;
+//!
+//! println!("{} new, {} already seen", report.fetched.len(), report.skipped);
+//!
+//! let mut policy = Retention::default().older_than(Duration::from_secs(30 * 24 * 3600));
+//! fetcher.apply(&mut policy).await?;
+//! fetcher.quit().await?;
+//! #    Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Result, UidlEntry};
+
+#[cfg(feature = "runtime-sync")]
+mod blocking;
+
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
+
+mod retention;
+
+pub use retention::Retention;
+
+/// Persistent record of the unique-ids seen per account
+///
+/// Calls are made from the async runtime too, so implementations should not block for long.
+pub trait UidStore {
+    /// Unique-ids recorded for `account`, with the time each was first seen
+    fn seen(&mut self, account: &str) -> Result<HashMap<String, SystemTime>>;
+
+    /// Record a unique-id for `account`, first seen at `seen`, durably once this returns
+    fn insert(&mut self, account: &str, uid: &str, seen: SystemTime) -> Result<()>;
+
+    /// Forget the unique-ids of `account` that are not in `uids`, i.e. of the messages no longer on the server
+    fn retain(&mut self, account: &str, uids: &HashSet<String>) -> Result<()>;
+}
+
+/// A store kept in memory, lost when dropped
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    accounts: HashMap<String, HashMap<String, SystemTime>>,
+}
+
+/// A store keeping the unique-ids of each account in a file of a directory,
+/// one per line followed by the time it was first seen, in seconds since the Unix epoch
+///
+/// Unique-ids are appended as they are recorded, and a line cut by a crash is ignored.
+/// Lines without a time, as written by earlier versions, are taken as first seen when read, and the time is then recorded.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+/// Outcome of a [`Fetcher`] run
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Report {
+    /// Messages retrieved and delivered to the sink
+    pub fetched:   Vec<UidlEntry>,
+    /// Messages on the server whose unique-id was already recorded
+    pub skipped:   usize,
+    /// Unique-ids forgotten because their message is no longer on the server
+    pub forgotten: usize,
+}
+
+/// Retrieves the messages not seen yet through a [`SyncClient`](crate::SyncClient) or an [`AsyncClient`](crate::AsyncClient)
+#[derive(Debug)]
+pub struct Fetcher<C, U> {
+    client:  C,
+    store:   U,
+    account: String,
+}
+
+impl MemoryStore {
+    /// An empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl UidStore for MemoryStore {
+    fn seen(&mut self, account: &str) -> Result<HashMap<String, SystemTime>> {
+        Ok(self.accounts.get(account).cloned().unwrap_or_default())
+    }
+
+    fn insert(&mut self, account: &str, uid: &str, seen: SystemTime) -> Result<()> {
+        self.accounts.entry(account.into()).or_default().entry(uid.into()).or_insert(seen);
+        Ok(())
+    }
+
+    fn retain(&mut self, account: &str, uids: &HashSet<String>) -> Result<()> {
+        if let Some(seen) = self.accounts.get_mut(account) {
+            seen.retain(|uid, _| uids.contains(uid));
+        }
+
+        Ok(())
+    }
+}
+
+impl FileStore {
+    /// Use the directory at `dir`, creating it if needed
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Directory of the store
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File of an account, named after it with the characters that are not safe in file names percent-encoded
+    fn file(&self, account: &str) -> PathBuf {
+        let mut name = String::with_capacity(account.len() + 5);
+
+        for b in account.bytes() {
+            match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'@' | b'-' | b'_' | b'.' => name.push(b as char),
+                _ => name.push_str(&format!("%{b:02X}")),
+            }
+        }
+
+        name.push_str(".uids");
+        self.dir.join(name)
+    }
+}
+
+impl UidStore for FileStore {
+    fn seen(&mut self, account: &str) -> Result<HashMap<String, SystemTime>> {
+        let content = match fs::read_to_string(self.file(account)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Only the lines ended by a newline are complete
+        let complete = content.rsplit_once('\n').map_or("", |(complete, _)| complete);
+
+        let mut seen = HashMap::new();
+        let mut untimed = false;
+        // In whole seconds, as recorded
+        let now = UNIX_EPOCH + Duration::from_secs(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+
+        for line in complete.split('\n').filter(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some((uid, secs)) => {
+                    if let Ok(secs) = secs.parse() {
+                        seen.entry(uid.to_string()).or_insert(UNIX_EPOCH + Duration::from_secs(secs));
+                    }
+                }
+                None => {
+                    seen.entry(line.to_string()).or_insert(now);
+                    untimed = true;
+                }
+            }
+        }
+
+        // Record the time given to the unique-ids of the earlier format, so their age counts from now on
+        if untimed {
+            self.rewrite(account, seen.iter())?;
+        }
+
+        Ok(seen)
+    }
+
+    fn insert(&mut self, account: &str, uid: &str, seen: SystemTime) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(self.file(account))?;
+
+        // Drop a line cut by a crash, as its unique-id may be the prefix of another
+        let len = file.metadata()?.len();
+        if len > 0 && last_byte(&mut file)? != b'\n' {
+            let mut content = Vec::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_end(&mut content)?;
+            file.set_len(content.iter().rposition(|&b| b == b'\n').map_or(0, |i| i as u64 + 1))?;
+        }
+
+        file.write_all(line(uid, seen).as_bytes())?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn retain(&mut self, account: &str, uids: &HashSet<String>) -> Result<()> {
+        let seen = self.seen(account)?;
+        let kept: Vec<_> = seen.iter().filter(|(uid, _)| uids.contains(*uid)).collect();
+
+        if kept.len() == seen.len() {
+            return Ok(());
+        }
+
+        self.rewrite(account, kept.into_iter())
+    }
+}
+
+impl FileStore {
+    /// Replace the file of an account atomically, so a crash leaves either list
+    fn rewrite<'a>(&self, account: &str, entries: impl Iterator<Item = (&'a String, &'a SystemTime)>) -> Result<()> {
+        let path = self.file(account);
+        let tmp = path.with_extension("uids.tmp");
+        let mut file = File::create(&tmp)?;
+
+        for (uid, seen) in entries {
+            file.write_all(line(uid, *seen).as_bytes())?;
+        }
+
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+}
+
+fn line(uid: &str, seen: SystemTime) -> String {
+    let secs = seen.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    format!("{uid} {secs}\n")
+}
+
+fn last_byte(file: &mut File) -> Result<u8> {
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+
+    Ok(last[0])
+}
+
+impl<C, U: UidStore> Fetcher<C, U> {
+    /// Fetch through an authorized `client`, recording the unique-ids under `account` in `store`
+    pub fn new(client: C, store: U, account: impl Into<String>) -> Self {
+        Self { client, store, account: account.into() }
+    }
+
+    /// The wrapped client
+    pub fn client(&mut self) -> &mut C {
+        &mut self.client
+    }
+
+    /// The store of unique-ids
+    pub fn store(&mut self) -> &mut U {
+        &mut self.store
+    }
+
+    /// The account the unique-ids are recorded under
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// The wrapped client and store
+    pub fn into_parts(self) -> (C, U) {
+        (self.client, self.store)
+    }
+
+    /// Forget the unique-ids no longer on the server, returning the entries of the messages to retrieve
+    fn plan(&mut self, entries: Vec<UidlEntry>, report: &mut Report) -> Result<Vec<UidlEntry>> {
+        let on_server: HashSet<String> = entries.iter().map(|entry| entry.uid.clone()).collect();
+
+        let seen = self.store.seen(&self.account)?;
+        report.forgotten = seen.keys().filter(|uid| !on_server.contains(*uid)).count();
+
+        if report.forgotten > 0 {
+            self.store.retain(&self.account, &on_server)?;
+        }
+
+        let unseen: Vec<UidlEntry> = entries
+            .into_iter()
+            .filter(|entry| !seen.contains_key(&entry.uid))
+            .collect();
+
+        report.skipped = on_server.len() - unseen.len();
+
+        Ok(unseen)
+    }
+
+    /// The delivered messages to delete under `policy`, among the `entries` listed, whose sizes are in `sizes`
+    fn expired(&mut self, entries: Vec<UidlEntry>, sizes: &HashMap<u64, u64>, policy: &mut Retention) -> Result<Vec<UidlEntry>> {
+        let seen = self.store.seen(&self.account)?;
+        let now = SystemTime::now();
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| match seen.get(&entry.uid) {
+                Some(&first) => policy.matches(entry, sizes.get(&entry.id).copied(), first, now),
+                None => false,
+            })
+            .collect())
+    }
+
+    /// Record a message delivered to the sink
+    fn delivered(&mut self, entry: UidlEntry, report: &mut Report) -> Result<()> {
+        self.store.insert(&self.account, &entry.uid, SystemTime::now())?;
+        report.fetched.push(entry);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uids(uids: &[&str]) -> HashSet<String> {
+        uids.iter().map(|uid| uid.to_string()).collect()
+    }
+
+    fn seen(store: &mut impl UidStore, account: &str) -> HashSet<String> {
+        store.seen(account).unwrap().into_keys().collect()
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn exercise(store: &mut impl UidStore) {
+        assert!(store.seen("alice@example.com").unwrap().is_empty());
+
+        store.insert("alice@example.com", "a1", at(1)).unwrap();
+        store.insert("alice@example.com", "a2", at(2)).unwrap();
+        store.insert("alice@example.com", "a3", at(3)).unwrap();
+        store.insert("bob/../example", "b1", at(4)).unwrap();
+
+        assert_eq!(seen(store, "alice@example.com"), uids(&["a1", "a2", "a3"]));
+        assert_eq!(seen(store, "bob/../example"), uids(&["b1"]));
+
+        store.retain("alice@example.com", &uids(&["a2", "a3", "a4"])).unwrap();
+        assert_eq!(seen(store, "alice@example.com"), uids(&["a2", "a3"]));
+        assert_eq!(seen(store, "bob/../example"), uids(&["b1"]));
+
+        // The first time seen is kept
+        store.insert("alice@example.com", "a2", at(5)).unwrap();
+        assert_eq!(store.seen("alice@example.com").unwrap()["a2"], at(2));
+    }
+
+    #[test]
+    fn memory_store() {
+        exercise(&mut MemoryStore::new());
+    }
+
+    #[test]
+    fn file_store() {
+        let dir = std::env::temp_dir().join(format!("pop3-uids-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut store = FileStore::open(&dir).unwrap();
+        exercise(&mut store);
+
+        // Account names do not escape the directory
+        assert!(dir.join("bob%2F..%2Fexample.uids").exists());
+
+        // A line cut by a crash is ignored, and the next one starts afresh
+        OpenOptions::new().append(true).open(store.file("alice@example.com")).unwrap().write_all(b"a5 1").unwrap();
+        assert_eq!(seen(&mut store, "alice@example.com"), uids(&["a2", "a3"]));
+        store.insert("alice@example.com", "a6", at(6)).unwrap();
+
+        let seen = FileStore::open(&dir).unwrap().seen("alice@example.com").unwrap();
+        assert_eq!(seen.len(), 3);
+        assert_eq!((seen["a3"], seen["a6"]), (at(3), at(6)));
+
+        // Unique-ids without a time are first seen when read, and keep that time
+        fs::write(store.file("carol"), "c1\nc2 7\n").unwrap();
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let seen = store.seen("carol").unwrap();
+        assert!(seen["c1"] >= before);
+        assert_eq!(seen["c2"], at(7));
+        assert_eq!(store.seen("carol").unwrap(), seen);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
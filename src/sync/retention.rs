@@ -0,0 +1,106 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::UidlEntry;
+
+type Confirm = Box<dyn FnMut(&UidlEntry) -> bool + Send>;
+
+/// Rules selecting the delivered messages to delete from the server
+///
+/// A message is deleted when any rule matches it, and only if its unique-id is recorded in the store,
+/// i.e. it was delivered to the sink. A policy without rules deletes nothing.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use pop3_client::sync::Retention;
+///
+/// let policy = Retention::default()
+///     .older_than(Duration::from_secs(30 * 24 * 3600))
+///     .larger_than(10 * 1024 * 1024);
+/// ```
+#[derive(Default)]
+pub struct Retention {
+    max_age:  Option<Duration>,
+    max_size: Option<u64>,
+    confirm:  Option<Confirm>,
+}
+
+impl Retention {
+    /// Delete the messages first seen at least `age` ago
+    pub fn older_than(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Delete the messages larger than `size` octets
+    pub fn larger_than(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Delete the messages for which `confirm` returns true, e.g. once the downstream store acknowledged them
+    pub fn confirmed_by<F>(mut self, confirm: F) -> Self
+    where
+        F: FnMut(&UidlEntry) -> bool + Send + 'static,
+    {
+        self.confirm = Some(Box::new(confirm));
+        self
+    }
+
+    /// Whether the rules need the sizes of the messages
+    pub(super) fn needs_sizes(&self) -> bool {
+        self.max_size.is_some()
+    }
+
+    /// Whether a delivered message is to be deleted
+    pub(super) fn matches(&mut self, entry: &UidlEntry, size: Option<u64>, seen: SystemTime, now: SystemTime) -> bool {
+        let old = self.max_age.is_some_and(|age| now.duration_since(seen).is_ok_and(|elapsed| elapsed >= age));
+        let large = self.max_size.zip(size).is_some_and(|(max, size)| size > max);
+
+        old || large || self.confirm.as_mut().is_some_and(|confirm| confirm(entry))
+    }
+}
+
+impl fmt::Debug for Retention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retention")
+            .field("max_age", &self.max_age)
+            .field("max_size", &self.max_size)
+            .field("confirm", &self.confirm.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let day = Duration::from_secs(86400);
+        let now = SystemTime::now();
+        let entry = UidlEntry { id: 1, uid: "u1".into() };
+        let other = UidlEntry { id: 2, uid: "u2".into() };
+
+        let mut none = Retention::default();
+        assert!(!none.matches(&entry, Some(u64::MAX), now - 365 * day, now));
+
+        let mut old = Retention::default().older_than(7 * day);
+        assert!(old.matches(&entry, None, now - 7 * day, now));
+        assert!(!old.matches(&entry, None, now - 6 * day, now));
+        assert!(!old.matches(&entry, None, now + day, now));
+
+        let mut large = Retention::default().larger_than(1000);
+        assert!(large.needs_sizes());
+        assert!(large.matches(&entry, Some(1001), now, now));
+        assert!(!large.matches(&entry, Some(1000), now, now));
+        assert!(!large.matches(&entry, None, now, now));
+
+        let mut confirmed = Retention::default().confirmed_by(|entry| entry.uid == "u1");
+        assert!(!confirmed.needs_sizes());
+        assert!(confirmed.matches(&entry, None, now, now));
+        assert!(!confirmed.matches(&other, None, now, now));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use ::tokio::io::{AsyncRead, AsyncWrite};
+
+use bytes::Bytes;
+
+use super::{Fetcher, Report, Retention, UidStore};
+use crate::{AsyncClient, Command, Response, Result, UidlEntry};
+
+impl<S: AsyncRead + AsyncWrite + Unpin, U: UidStore> Fetcher<AsyncClient<S>, U> {
+    /// Retrieve the messages not seen yet and hand them to `sink`, in the order of the listing
+    ///
+    /// The unique-id of a message is recorded once `sink` returns successfully. The run stops at the first error,
+    /// leaving the failed message and the following ones for the next run.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// use pop3_client::sync::{Fetcher, MemoryStore};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// let mut client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// client.login("sweet_username", "very_secret_password").await?;
+    ///
+    /// let mut fetcher = Fetcher::new(client, MemoryStore::new(), "sweet_username");
+    /// let report = fetcher.run(|entry, message| async move {
+    ///     println!("{}: {} octets", entry.uid, message.len());
+    ///     Ok(())
+    /// }).await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub async fn run<F, Fut>(&mut self, mut sink: F) -> Result<Report>
+    where
+        F: FnMut(UidlEntry, Bytes) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut report = Report::default();
+        let entries = self.client.uidl_all().await?;
+
+        for entry in self.plan(entries, &mut report)? {
+            let message = self.client.retr(entry.id).await?;
+            sink(entry.clone(), message).await?;
+            self.delivered(entry, &mut report)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Mark for deletion the delivered messages matching `policy`, returning their entries each with the reply to its `DELE`
+    ///
+    /// The server only deletes them once the session ends with [`quit`](Self::quit). The commands are pipelined if the server supports it.
+    /// A message the server refuses to mark does not affect the others, see [`pipeline`](crate::AsyncClient::pipeline).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::result::Result;
+    /// # use pop3_client::{AsyncClient, Pop3Error};
+    /// use pop3_client::sync::{Fetcher, MemoryStore, Retention};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Pop3Error> {
+    /// # let client = AsyncClient::connect("pop3.mailtrap.io", 1100).await?;
+    /// # let mut fetcher = Fetcher::new(client, MemoryStore::new(), "sweet_username");
+    /// let mut policy = Retention::default().larger_than(10 * 1024 * 1024);
+    /// for (entry, reply) in fetcher.apply(&mut policy).await? {
+    ///     if let Err(e) = reply {
+    ///         eprintln!("{} is kept: {e}", entry.uid);
+    ///     }
+    /// }
+    /// fetcher.quit().await?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub async fn apply(&mut self, policy: &mut Retention) -> Result<Vec<(UidlEntry, Result<Response>)>> {
+        let entries = self.client.uidl_all().await?;
+        let sizes: HashMap<u64, u64> = if policy.needs_sizes() {
+            self.client.list_all().await?.into_iter().map(|entry| (entry.id, entry.size)).collect()
+        } else {
+            HashMap::new()
+        };
+
+        let expired = self.expired(entries, &sizes, policy)?;
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let commands: Vec<_> = expired.iter().map(|entry| Command::Dele { id: entry.id }).collect();
+        let replies = self.client.pipeline(&commands).await;
+
+        Ok(expired.into_iter().zip(replies).collect())
+    }
+
+    /// End the session, committing the deletions, and return the store
+    pub async fn quit(self) -> Result<U> {
+        self.client.quit().await?;
+        Ok(self.store)
+    }
+}
@@ -1,21 +1,895 @@
+mod common;
+
 #[cfg(test)]
 #[cfg(feature = "runtime-tokio")]
 mod tests {
+    use std::time::Duration;
+
     use pop3_client::*;
 
+    use crate::common::{Step, TestServer};
+
 
     async fn tokio_connect() -> Result<AsyncClient> {
         AsyncClient::connect("pop3.mailtrap.io", 1100).await
     }
 
+    #[tokio::test]
+    async fn tokio_connects() {
+        assert!(tokio_connect().await.is_ok());
+    }
+
     #[cfg(feature = "with-rustls")]
-    fn connect() -> Result<Client> {
-        pop3_client::Builder::default().connect("pop3.mailtrap.io", 1100)
+    #[tokio::test]
+    #[ignore = "requires network access to pop3.mailtrap.io"]
+    async fn tls_connects() {
+        assert!(AsyncClient::connect_tls("pop3.mailtrap.io", 9950).await.is_ok());
     }
 
+    #[cfg(feature = "with-rustls")]
     #[tokio::test]
-    async fn tokio_connects() {
-        assert!(tokio_connect().await.is_ok());
+    async fn tls_custom_config() {
+        let server = TestServer::spawn(true, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("NOOP\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("QUIT\r\n"),
+            Step::Send("+OK bye\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .security(Security::Tls)
+            .rustls_config(server.client_config())
+            .connect_async()
+            .await
+            .unwrap();
+
+        client.noop().await.unwrap();
+        client.quit().await.unwrap();
+        server.join();
+    }
+
+    #[cfg(feature = "with-rustls")]
+    #[tokio::test]
+    async fn tls_untrusted_certificate() {
+        let server = TestServer::spawn(true, vec![]);
+        let result = Builder::new("localhost").port(server.port).security(Security::Tls).connect_async().await;
+        eprintln!("tls_untrusted_certificate: {:?}", result.as_ref().err());
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "with-rustls")]
+    #[tokio::test]
+    async fn stls_upgrade() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("STLS\r\n"),
+            Step::Send("+OK Begin TLS negotiation\r\n"),
+            Step::StartTls,
+            Step::Expect("NOOP\r\n"),
+            Step::Send("+OK\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .security(Security::StlsRequired)
+            .rustls_config(server.client_config())
+            .connect_async()
+            .await
+            .unwrap();
+
+        client.noop().await.unwrap();
+        server.join();
+    }
+
+    #[cfg(feature = "with-rustls")]
+    #[tokio::test]
+    async fn stls_discards_injected_data() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("STLS\r\n"),
+            Step::Send("+OK Begin TLS negotiation\r\n-ERR injected\r\n"),
+            Step::StartTls,
+            Step::Expect("NOOP\r\n"),
+            Step::Send("+OK\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .security(Security::StlsRequired)
+            .rustls_config(server.client_config())
+            .connect_async()
+            .await
+            .unwrap();
+
+        let result = client.noop().await;
+        eprintln!("stls_discards_injected_data: {:?}", result);
+        assert!(result.is_ok());
+        server.join();
+    }
+
+    #[cfg(feature = "with-rustls")]
+    #[tokio::test]
+    async fn stls_wrong_stage() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("USER user\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("PASS pass\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("QUIT\r\n"),
+            Step::Send("+OK\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        client.login("user", "pass").await.unwrap();
+
+        let result = client.stls().await;
+        eprintln!("stls_wrong_stage: {:?}", result);
+        assert!(matches!(result, Err(Pop3Error::AlreadyAuthenticated)));
+
+        client.quit().await.unwrap();
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn capa_cached() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK Capability list follows\r\nUSER\r\nUIDL\r\nSASL PLAIN\r\n.\r\n"),
+            Step::Expect("USER user\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("PASS pass\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nUIDL\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let capa = client.capa().await.unwrap();
+        assert!(capa.supports_user());
+        assert!(capa.supports_uidl());
+        assert_eq!(capa.sasl_mechanisms(), ["PLAIN"]);
+        assert_eq!(client.capa().await.unwrap(), capa);
+
+        client.login("user", "pass").await.unwrap();
+
+        let capa = client.capa().await.unwrap();
+        assert!(!capa.supports_user());
+        assert!(capa.supports_uidl());
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_plain() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH PLAIN AHVzZXIAcGFzcw==\r\n"),
+            Step::Send("+OK maildrop locked and ready\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        client.authenticate(sasl::Plain::new("user", "pass")).await.unwrap();
+
+        let result = client.authenticate(sasl::Plain::new("user", "pass")).await;
+        assert!(matches!(result, Err(Pop3Error::AlreadyAuthenticated)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_login() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH LOGIN\r\n"),
+            Step::Send("+ VXNlcm5hbWU6\r\n"),
+            Step::Expect("dXNlcg==\r\n"),
+            Step::Send("+ UGFzc3dvcmQ6\r\n"),
+            Step::Expect("cGFzcw==\r\n"),
+            Step::Send("+OK maildrop locked and ready\r\n"),
+            Step::Expect("STAT\r\n"),
+            Step::Send("+OK 2 320\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        client.authenticate(sasl::Login::new("user", "pass")).await.unwrap();
+        assert_eq!(client.stat().await.unwrap(), (2, 320));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_cancelled() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH LOGIN\r\n"),
+            Step::Send("+ not base64!\r\n"),
+            Step::Expect("*\r\n"),
+            Step::Send("-ERR authentication cancelled\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let result = client.authenticate(sasl::Login::new("user", "pass")).await;
+        eprintln!("auth_cancelled: {:?}", result);
+        assert!(matches!(result, Err(Pop3Error::Sasl(_))));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_rejected() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH PLAIN AHVzZXIAcGFzcw==\r\n"),
+            Step::Send("-ERR authentication failed\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let result = client.authenticate(sasl::Plain::new("user", "pass")).await;
+        eprintln!("auth_rejected: {:?}", result);
+        assert!(result.is_err());
+        assert!(!matches!(result.unwrap_err(), Pop3Error::ConnectionClosed));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_xoauth2_error() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH XOAUTH2 dXNlcj11c2VyQGV4YW1wbGUuY29tAWF1dGg9QmVhcmVyIHRva2VuAQE=\r\n"),
+            Step::Send("+ eyJzdGF0dXMiOiI0MDEiLCJzY2hlbWVzIjoiYmVhcmVyIiwic2NvcGUiOiJodHRwczovL21haWwuZ29vZ2xlLmNvbS8ifQ==\r\n"),
+            Step::Expect("\r\n"),
+            Step::Send("-ERR invalid credentials\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let result = client.authenticate(sasl::XOAuth2::new("user@example.com", "token")).await;
+        eprintln!("auth_xoauth2_error: {:?}", result);
+        assert!(matches!(
+            result,
+            Err(Pop3Error::OAuth { status, scope: Some(scope), .. }) if status == "401" && scope == "https://mail.google.com/"
+        ));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_cram_md5() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("AUTH CRAM-MD5\r\n"),
+            Step::Send("+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n"),
+            Step::Expect("dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n"),
+            Step::Send("+OK maildrop locked and ready\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        client.authenticate(sasl::CramMd5::new("tim", "tanstaaftanstaaf")).await.unwrap();
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn auth_scram_unverified() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Receive,
+            // A reply ready for the next command
+            Step::Send("+OK maildrop locked and ready\r\n+OK\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let result = client.authenticate(sasl::Scram::sha256("user", "pencil")).await;
+        eprintln!("auth_scram_unverified: {:?}", result);
+        assert!(matches!(result, Err(Pop3Error::Sasl(_))));
+
+        // The server was not authenticated, so the session is not used
+        assert!(matches!(client.noop().await, Err(Pop3Error::ConnectionClosed)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn apop_with_password() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n"),
+            Step::Expect("APOP mrose c4c9334bac560ecc979e58001b3e22fb\r\n"),
+            Step::Send("+OK maildrop has 1 message (369 octets)\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        assert_eq!(client.greeting(), "POP3 server ready <1896.697170952@dbc.mtview.ca.us>");
+        client.apop_with_password("mrose", "tanstaaf").await.unwrap();
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn apop_no_timestamp() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let result = client.apop_with_password("mrose", "tanstaaf").await;
+        assert!(matches!(result, Err(Pop3Error::NoApopTimestamp)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn retr_dot_stuffed() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n...signature\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let message = client.retr(1).await.unwrap();
+        assert_eq!(&message[..], b"Subject: dots\r\n\r\n.\r\n..signature\r\n");
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("NOOP\r\n"),
+            Step::Sleep(500),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .read_timeout(Duration::from_millis(100))
+            .connect_async()
+            .await
+            .unwrap();
+
+        let result = client.noop().await;
+        eprintln!("read_timeout: {:?}", result);
+        assert!(matches!(result, Err(Pop3Error::Timeout { command: "NOOP" })));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn late_reply_after_timeout() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("NOOP\r\n"),
+            Step::Sleep(300),
+            Step::Send("+OK late\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .read_timeout(Duration::from_millis(100))
+            .connect_async()
+            .await
+            .unwrap();
+
+        assert!(matches!(client.noop().await, Err(Pop3Error::Timeout { command: "NOOP" })));
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(matches!(client.noop().await, Err(Pop3Error::ConnectionClosed)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn session_timeout() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("NOOP\r\n"),
+            Step::Sleep(500),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .read_timeout(Duration::from_secs(5))
+            .session_timeout(Duration::from_millis(200))
+            .connect_async()
+            .await
+            .unwrap();
+
+        assert!(matches!(client.noop().await, Err(Pop3Error::Timeout { command: "NOOP" })));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn builder_password() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nUSER\r\nSASL LOGIN PLAIN\r\n.\r\n"),
+            Step::Expect("AUTH PLAIN AHVzZXIAcGFzcw==\r\n"),
+            Step::Send("+OK maildrop locked and ready\r\n"),
+        ]);
+
+        Builder::new("localhost")
+            .port(server.port)
+            .password("user", "pass")
+            .connect_async()
+            .await
+            .unwrap();
+
+        server.join();
+    }
+
+    #[cfg(feature = "with-rustls")]
+    #[tokio::test]
+    async fn builder_stls_opportunistic() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nSTLS\r\n.\r\n"),
+            Step::Expect("STLS\r\n"),
+            Step::Send("+OK Begin TLS negotiation\r\n"),
+            Step::StartTls,
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nUSER\r\n.\r\n"),
+            Step::Expect("USER user\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("PASS pass\r\n"),
+            Step::Send("+OK\r\n"),
+        ]);
+
+        Builder::new("localhost")
+            .port(server.port)
+            .security(Security::StlsOpportunistic)
+            .rustls_config(server.client_config())
+            .password("user", "pass")
+            .connect_async()
+            .await
+            .unwrap();
+
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn from_stream() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (stream, server) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            server.write_all(b"+OK POP3 server ready\r\n").await.unwrap();
+
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "NOOP\r\n");
+            server.write_all(b"+OK\r\n").await.unwrap();
+        });
+
+        let mut client = AsyncClient::from_stream(stream).await.unwrap();
+        assert_eq!(client.greeting(), "POP3 server ready");
+        client.noop().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retr_stream() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n"),
+            Step::Sleep(50),
+            Step::Send("...signature\r\n.\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n...signature\r\n.\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n...signature\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let mut message = Vec::new();
+        let mut stream = client.retr_stream(1).await.unwrap();
+        while let Some(chunk) = stream.next_chunk().await {
+            message.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(message, b"Subject: dots\r\n\r\n.\r\n..signature\r\n");
+
+        // The rest of an abandoned message is skipped
+        let mut stream = client.retr_stream(1).await.unwrap();
+        stream.next_chunk().await.unwrap().unwrap();
+        drop(stream);
+
+        let mut message = Vec::new();
+        assert_eq!(client.retr_to_writer(1, &mut message).await.unwrap(), 33);
+        assert_eq!(message, b"Subject: dots\r\n\r\n.\r\n..signature\r\n");
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn retr_stream_timeout() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK\r\nSubject: late\r\n"),
+            Step::Sleep(300),
+            Step::Send("\r\nbody\r\n.\r\n+OK\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .read_timeout(Duration::from_millis(100))
+            .connect_async()
+            .await
+            .unwrap();
+
+        let mut stream = client.retr_stream(1).await.unwrap();
+        stream.next_chunk().await.unwrap().unwrap();
+        assert!(matches!(stream.next_chunk().await, Some(Err(Pop3Error::Timeout { command: "RETR" }))));
+        drop(stream);
+
+        // The rest of the message and a stray reply arrive, but are not taken for the next reply
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(matches!(client.noop().await, Err(Pop3Error::ConnectionClosed)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn response_too_large() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n...signature\r\n.\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK 36 octets\r\nSubject: dots\r\n\r\n..\r\n...signature\r\n.\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .max_response_size(32)
+            .connect_async()
+            .await
+            .unwrap();
+
+        let message = client.retr_with_limit(1, 64).await.unwrap();
+        assert_eq!(&message[..], b"Subject: dots\r\n\r\n.\r\n..signature\r\n");
+
+        assert!(matches!(client.retr(1).await, Err(Pop3Error::ResponseTooLarge { command: "RETR", limit: 32 })));
+        assert!(matches!(client.noop().await, Err(Pop3Error::ConnectionClosed)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn session_typestate() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("USER user\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("PASS wrong\r\n"),
+            Step::Send("-ERR [AUTH] invalid password\r\n"),
+            Step::Expect("USER user\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("PASS pass\r\n"),
+            Step::Send("+OK maildrop locked and ready\r\n"),
+            Step::Expect("STAT\r\n"),
+            Step::Send("+OK 2 320\r\n"),
+            Step::Expect("QUIT\r\n"),
+            Step::Send("+OK bye\r\n"),
+        ]);
+
+        let client = session::Client::new(AsyncClient::connect("localhost", server.port).await.unwrap()).unwrap();
+
+        let rejected = client.login("user", "wrong").await.unwrap_err();
+        assert_eq!(rejected.error.code(), Some(&ResponseCode::Auth));
+
+        let mut client = rejected.client.login("user", "pass").await.unwrap();
+        assert_eq!(client.stat().await.unwrap(), (2, 320));
+        client.quit().await.unwrap();
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn pipeline() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nPIPELINING\r\n.\r\n"),
+            // All commands arrive before any reply is sent
+            Step::Expect("RETR 1\r\n"),
+            Step::Expect("DELE 1\r\n"),
+            Step::Expect("RETR 2\r\n"),
+            Step::Send("+OK 7 octets\r\nhello\r\n.\r\n+OK deleted\r\n-ERR no such message\r\n"),
+        ]);
+
+        let mut client = Builder::new("localhost")
+            .port(server.port)
+            .read_timeout(Duration::from_secs(2))
+            .connect_async()
+            .await
+            .unwrap();
+
+        let results = client.pipeline(&[
+            Command::Retr { id: 1 },
+            Command::Dele { id: 1 },
+            Command::User { data: "user" },
+            Command::Retr { id: 2 },
+        ]).await;
+
+        assert_eq!(&results[0].as_ref().unwrap().raw()[..], b"7 octets\r\nhello\r\n");
+        assert!(results[1].is_ok());
+        assert!(matches!(results[2], Err(Pop3Error::NotPipelinable { command: "USER" })));
+        assert!(matches!(&results[3], Err(Pop3Error::Server { text, .. }) if text == "no such message"));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn pipeline_connection_lost() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nPIPELINING\r\n.\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Expect("RETR 2\r\n"),
+            Step::Expect("RETR 3\r\n"),
+            // The connection drops after the first reply
+            Step::Send("+OK\r\nhello\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let results = client.pipeline(&[Command::Retr { id: 1 }, Command::Retr { id: 2 }, Command::Retr { id: 3 }]).await;
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Pop3Error::ConnectionClosed | Pop3Error::Io(_))));
+        assert!(matches!(results[2], Err(Pop3Error::ConnectionClosed)));
+
+        // The session is over, rather than waiting for the replies to RETR 2 and 3
+        assert!(matches!(client.noop().await, Err(Pop3Error::ConnectionClosed)));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn pipeline_lock_step() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("-ERR unknown command\r\n"),
+            Step::Expect("STAT\r\n"),
+            Step::Send("+OK 2 320\r\n"),
+            Step::Expect("NOOP\r\n"),
+            Step::Send("+OK\r\n"),
+            // The rejected CAPA is not sent again
+            Step::Expect("NOOP\r\n"),
+            Step::Send("+OK\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let results = client.pipeline(&[Command::Stat, Command::Noop]).await;
+        assert_eq!(&results[0].as_ref().unwrap().raw()[..], b"2 320\r\n");
+        assert!(results[1].is_ok());
+
+        let results = client.pipeline(&[Command::Noop]).await;
+        assert!(results[0].is_ok());
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn headers() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("TOP 1 0\r\n"),
+            Step::Send("+OK\r\nMessage-ID: <1@example.com>\r\nSubject: =?UTF-8?Q?caf=C3=A9?=\r\n\tmenu\r\nFrom: Bob <bob@example.com>\r\n\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let headers = client.headers(1).await.unwrap();
+        assert_eq!(headers.message_id(), Some("1@example.com"));
+        assert_eq!(headers.subject().as_deref(), Some("café\tmenu"));
+        assert_eq!(headers.from()[0].email, "bob@example.com");
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn incremental_fetch() {
+        use std::time::SystemTime;
+        use pop3_client::sync::{Fetcher, MemoryStore, UidStore};
+
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 u1\r\n2 u2\r\n3 u3\r\n.\r\n"),
+            Step::Expect("RETR 2\r\n"),
+            Step::Send("+OK\r\nSubject: two\r\n.\r\n"),
+            Step::Expect("RETR 3\r\n"),
+            Step::Send("+OK\r\nSubject: three\r\n.\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 u1\r\n2 u2\r\n3 u3\r\n4 u4\r\n.\r\n"),
+            Step::Expect("RETR 4\r\n"),
+            Step::Send("+OK\r\nSubject: four\r\n.\r\n"),
+        ]);
+
+        let mut store = MemoryStore::new();
+        store.insert("alice", "u1", SystemTime::now()).unwrap();
+        store.insert("alice", "gone", SystemTime::now()).unwrap();
+
+        let client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let mut fetcher = Fetcher::new(client, store, "alice");
+
+        let mut delivered = Vec::new();
+        let report = fetcher.run(|entry, message| {
+            delivered.push((entry.uid, message));
+            async { Ok(()) }
+        }).await.unwrap();
+
+        assert_eq!(delivered, [("u2".to_string(), "Subject: two\r\n".into()), ("u3".to_string(), "Subject: three\r\n".into())]);
+        assert_eq!(report.fetched, [UidlEntry { id: 2, uid: "u2".into() }, UidlEntry { id: 3, uid: "u3".into() }]);
+        assert_eq!((report.skipped, report.forgotten), (1, 1));
+
+        // A failed delivery is not recorded
+        let result = fetcher.run(|_, _| async { Err(Pop3Error::ConnectionClosed) }).await;
+        assert!(matches!(result, Err(Pop3Error::ConnectionClosed)));
+        assert_eq!(fetcher.store().seen("alice").unwrap().len(), 3);
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn retention() {
+        use std::time::SystemTime;
+        use pop3_client::sync::{Fetcher, MemoryStore, Retention, UidStore};
+
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 u1\r\n2 u2\r\n3 u3\r\n4 u4\r\n.\r\n"),
+            Step::Expect("LIST\r\n"),
+            Step::Send("+OK\r\n1 100\r\n2 5000\r\n3 9000\r\n4 10\r\n.\r\n"),
+            Step::Expect("CAPA\r\n"),
+            Step::Send("+OK\r\nUIDL\r\n.\r\n"),
+            Step::Expect("DELE 1\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("DELE 2\r\n"),
+            Step::Send("-ERR message locked\r\n"),
+            Step::Expect("DELE 4\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("QUIT\r\n"),
+            Step::Send("+OK bye\r\n"),
+        ]);
+
+        let now = SystemTime::now();
+        let mut store = MemoryStore::new();
+        store.insert("alice", "u1", now - Duration::from_secs(10 * 86400)).unwrap();
+        store.insert("alice", "u2", now).unwrap();
+        store.insert("alice", "u4", now).unwrap();
+
+        let client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        let mut fetcher = Fetcher::new(client, store, "alice");
+
+        // The large u3 is kept, as it was not delivered
+        let mut policy = Retention::default()
+            .older_than(Duration::from_secs(7 * 86400))
+            .larger_than(1000)
+            .confirmed_by(|entry| entry.uid == "u4");
+
+        // A refused deletion does not stop the others
+        let marked = fetcher.apply(&mut policy).await.unwrap();
+        assert_eq!(marked.iter().map(|(entry, _)| entry.id).collect::<Vec<_>>(), [1, 2, 4]);
+        assert!(marked[0].1.is_ok() && marked[2].1.is_ok());
+        assert!(matches!(&marked[1].1, Err(Pop3Error::Server { text, .. }) if text == "message locked"));
+
+        fetcher.quit().await.unwrap();
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        use pop3_client::reconnect::{Backoff, Resilient};
+
+        let first = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 a\r\n2 b\r\n3 c\r\n.\r\n"),
+            Step::Expect("DELE 1\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("RETR 3\r\n"),
+        ]);
+
+        // Message b was deleted by another session meanwhile
+        let second = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 a\r\n2 c\r\n3 d\r\n.\r\n"),
+            Step::Expect("RETR 2\r\n"),
+            Step::Send("+OK\r\nSubject: c\r\n.\r\n"),
+            Step::Expect("LIST\r\n"),
+            Step::Send("+OK\r\n1 10\r\n2 20\r\n3 30\r\n.\r\n"),
+            Step::Expect("QUIT\r\n"),
+            Step::Send("+OK bye\r\n"),
+        ]);
+
+        let mut ports = vec![first.port, second.port].into_iter();
+        let connect = move || {
+            let port = ports.next().unwrap();
+            async move { AsyncClient::connect("localhost", port).await }
+        };
+        let backoff = Backoff { initial: Duration::from_millis(10), ..Backoff::default() };
+
+        let mut client = Resilient::connect_async(connect, backoff).await.unwrap();
+        client.dele(1).await.unwrap();
+
+        // The deletion is not replayed, and reported once reconnected
+        let result = client.retr(3).await;
+        assert!(matches!(result, Err(Pop3Error::DeletionsLost { ids }) if ids == [1]));
+
+        // Numbers are those of the first session
+        assert_eq!(client.retr(3).await.unwrap(), "Subject: c\r\n");
+        assert!(matches!(client.retr(2).await, Err(Pop3Error::UnknownMessage { id: 2 })));
+        assert_eq!(client.list_all().await.unwrap(), [ListEntry { id: 1, size: 10 }, ListEntry { id: 3, size: 20 }]);
+
+        client.quit().await.unwrap();
+        first.join();
+        second.join();
+    }
+
+    #[tokio::test]
+    async fn reconnect_rset() {
+        use pop3_client::reconnect::{Backoff, Resilient};
+
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 a\r\n2 b\r\n.\r\n"),
+            Step::Expect("DELE 1\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n2 b\r\n.\r\n"),
+            Step::Expect("RSET\r\n"),
+            Step::Send("+OK\r\n"),
+            Step::Expect("RETR 1\r\n"),
+            Step::Send("+OK\r\nSubject: a\r\n.\r\n"),
+        ]);
+
+        let port = server.port;
+        let connect = move || async move { AsyncClient::connect("localhost", port).await };
+        let mut client = Resilient::connect_async(connect, Backoff::default()).await.unwrap();
+
+        client.dele(1).await.unwrap();
+        assert_eq!(client.uidl_all().await.unwrap(), [UidlEntry { id: 2, uid: "b".into() }]);
+
+        // The message omitted by the listing is restored
+        client.rset().await.unwrap();
+        assert_eq!(client.retr(1).await.unwrap(), "Subject: a\r\n");
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn list_typed() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("LIST\r\n"),
+            Step::Send("+OK 2 messages (320 octets)\r\n1 120\r\n2 200\r\n.\r\n"),
+            Step::Expect("LIST 2\r\n"),
+            Step::Send("+OK 2 200\r\n"),
+            Step::Expect("LIST 3\r\n"),
+            Step::Send("+OK 3 big\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+        assert_eq!(client.list_all().await.unwrap(), [ListEntry { id: 1, size: 120 }, ListEntry { id: 2, size: 200 }]);
+        assert_eq!(client.list_one(2).await.unwrap(), ListEntry { id: 2, size: 200 });
+        assert!(matches!(client.list_one(3).await, Err(Pop3Error::MalformedLine(_))));
+        server.join();
+    }
+
+    #[tokio::test]
+    async fn uidl_typed() {
+        let server = TestServer::spawn(false, vec![
+            Step::Send("+OK POP3 server ready\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 whqtswO00WBw418f9t5JxYwZ\r\n2 QhdPYR:00WBw1Ph7x7\r\n.\r\n"),
+            Step::Expect("UIDL 2\r\n"),
+            Step::Send("+OK 2 QhdPYR:00WBw1Ph7x7\r\n"),
+            Step::Expect("UIDL\r\n"),
+            Step::Send("+OK\r\n1 has space\r\n.\r\n"),
+        ]);
+
+        let mut client = AsyncClient::connect("localhost", server.port).await.unwrap();
+
+        let uids = client.uidl_all().await.unwrap();
+        assert_eq!(uids.len(), 2);
+        assert_eq!(uids[0], UidlEntry { id: 1, uid: "whqtswO00WBw418f9t5JxYwZ".into() });
+        assert_eq!(client.uidl_one(2).await.unwrap().uid, "QhdPYR:00WBw1Ph7x7");
+        assert!(matches!(client.uidl_all().await, Err(Pop3Error::MalformedLine(_))));
+        server.join();
     }
 
     #[tokio::test]
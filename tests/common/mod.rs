@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+
+//! Scripted in-process POP3 server used by the TLS tests.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ClientConfig, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+/// One step of the conversation the server plays back
+pub enum Step {
+    /// Write the line to the client
+    Send(&'static str),
+    /// Read a line from the client and assert it equals the given one
+    Expect(&'static str),
+    /// Read a line from the client without checking it
+    Receive,
+    /// Upgrade the connection to TLS in place
+    StartTls,
+    /// Stall for the given number of milliseconds
+    Sleep(u64),
+}
+
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+pub struct TestServer {
+    pub port:   u16,
+    pub cert:   CertificateDer<'static>,
+    handle:     Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Spawn a server listening on localhost, which serves a single connection according to `script`
+    pub fn spawn(implicit_tls: bool, script: Vec<Step>) -> Self {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+
+        let cert = cert.der().clone();
+        let key  = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_no_client_auth()
+                .with_single_cert(vec![cert.clone()], key)
+                .unwrap()
+        );
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = std::thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            serve(socket, config, implicit_tls, script);
+        });
+
+        Self { port, cert, handle: Some(handle) }
+    }
+
+    /// Client config trusting only this server's certificate
+    pub fn client_config(&self) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.clone()).unwrap();
+
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    }
+
+    /// Wait for the server to finish its script, propagating its assertion failures
+    pub fn join(mut self) {
+        if let Some(handle) = self.handle.take() {
+            if let Err(e) = handle.join() {
+                std::panic::resume_unwind(e)
+            }
+        }
+    }
+}
+
+fn tls(socket: TcpStream, config: &Arc<ServerConfig>) -> Box<dyn Transport> {
+    let session = ServerConnection::new(config.clone()).unwrap();
+    Box::new(StreamOwned::new(session, socket))
+}
+
+fn serve(socket: TcpStream, config: Arc<ServerConfig>, implicit_tls: bool, script: Vec<Step>) {
+    let mut stream = if implicit_tls {
+        BufReader::new(tls(socket.try_clone().unwrap(), &config))
+    } else {
+        BufReader::new(Box::new(socket.try_clone().unwrap()) as Box<dyn Transport>)
+    };
+
+    for step in script {
+        match step {
+            Step::Send(line) => {
+                stream.get_mut().write_all(line.as_bytes()).unwrap();
+                stream.get_mut().flush().unwrap();
+            }
+            Step::Expect(line) => {
+                let mut buf = String::new();
+                stream.read_line(&mut buf).unwrap();
+                assert_eq!(buf, line);
+            }
+            Step::Receive => {
+                stream.read_line(&mut String::new()).unwrap();
+            }
+            Step::StartTls => {
+                stream = BufReader::new(tls(socket.try_clone().unwrap(), &config));
+            }
+            Step::Sleep(ms) => {
+                std::thread::sleep(std::time::Duration::from_millis(ms));
+            }
+        }
+    }
+}